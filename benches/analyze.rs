@@ -0,0 +1,124 @@
+//! Throughput and allocations of `analyze_transcript` on synthetic transcripts.
+//!
+//! Runs on stable without extra dependencies:
+//!
+//! ```text
+//! cargo bench --bench analyze             # small and large cases
+//! cargo bench --bench analyze -- 250000   # one case of that many lines
+//! ```
+
+use session_reflect::{analyze_transcript, Config};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counts every allocation so a run can report how many it made.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const SMALL: usize = 1_000;
+const LARGE: usize = 100_000;
+
+/// Keep sampling until this much time has been spent on a case.
+const BUDGET: Duration = Duration::from_secs(2);
+
+/// A transcript of `lines` entries in a realistic mix: user messages, tool
+/// calls with their results, plain replies and the odd memory write.
+fn transcript(lines: usize) -> String {
+    let mut out = String::new();
+    for i in 0..lines {
+        let line = match i % 10 {
+            0 => r#"{"type":"user","message":{"content":"Please refactor the parser and run the tests."}}"#.to_string(),
+            1 | 5 => format!(
+                r#"{{"type":"assistant","message":{{"content":[{{"type":"text","text":"Running step {}."}},{{"type":"tool_use","name":"Bash","input":{{"command":"cargo test --workspace --quiet -- parser::case_{}"}}}}]}}}}"#,
+                i, i
+            ),
+            2 | 6 => r#"{"type":"user","message":{"content":[{"type":"tool_result","content":"test result: ok. 42 passed; 0 failed"}]}}"#.to_string(),
+            3 => format!(
+                r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","name":"Edit","input":{{"file_path":"src/parser_{}.rs","old_string":"fn old()","new_string":"fn new()"}}}}]}}}}"#,
+                i % 50
+            ),
+            4 => r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Read","input":{"file_path":"src/lib.rs"}}]}}"#.to_string(),
+            7 => r#"{"type":"user","message":{"content":[{"type":"tool_result","is_error":true,"content":"error[E0308]: mismatched types"}]}}"#.to_string(),
+            8 if i % 1000 == 8 => r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Write","input":{"file_path":"Memory/Learnings/parser.md","content":"Parsers need fuzzing."}}]}}"#.to_string(),
+            _ => r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Done; the parser now handles nested input without recursion."}]}}"#.to_string(),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+fn bench(lines: usize, config: &Config) {
+    let input = transcript(lines);
+    let mut runs = 0u32;
+    let mut best = Duration::MAX;
+    let mut total = Duration::ZERO;
+    let mut allocations = 0;
+    let mut allocated = 0;
+
+    while total < BUDGET || runs < 3 {
+        let (count_before, bytes_before) = (
+            ALLOCATIONS.load(Ordering::Relaxed),
+            ALLOCATED_BYTES.load(Ordering::Relaxed),
+        );
+        let start = Instant::now();
+        black_box(analyze_transcript(black_box(input.as_bytes()), config));
+        let elapsed = start.elapsed();
+        allocations = ALLOCATIONS.load(Ordering::Relaxed) - count_before;
+        allocated = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes_before;
+
+        runs += 1;
+        best = best.min(elapsed);
+        total += elapsed;
+    }
+
+    let mean = total / runs;
+    let throughput = input.len() as f64 / mean.as_secs_f64() / (1024.0 * 1024.0);
+    println!(
+        "analyze_transcript/{:<8} {:>10} bytes  mean {:>10.3?}  best {:>10.3?}  {:>6.1} MiB/s  {:>8} allocs  {:>10} bytes allocated",
+        lines, input.len(), mean, best, throughput, allocations, allocated
+    );
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; any number is a line count
+    let sizes: Vec<usize> = std::env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse().ok())
+        .collect();
+    let sizes = if sizes.is_empty() {
+        vec![SMALL, LARGE]
+    } else {
+        sizes
+    };
+
+    let config = Config::default();
+    for lines in sizes {
+        bench(lines, &config);
+    }
+}
@@ -0,0 +1,2134 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use crate::clock::parse_rfc3339;
+use crate::config::{CaptureRule, Config};
+use crate::pattern::{frontmatter_tags, read_text};
+
+pub const MEMORY_PATHS: &[&str] = &["Memory/Learnings/", "Memory/Decisions/"];
+
+/// Input fields that patch-style edit tools carry their diff in.
+const PATCH_FIELDS: &[&str] = &["patch", "diff", "input"];
+
+/// `Write`/`Edit` input fields carrying the written text inline.
+const INLINE_CONTENT_FIELDS: &[&str] = &["content", "new_string"];
+
+/// Work-type labels, in tie-break order, for `Analysis::work_type`.
+pub const WORK_TYPES: &[&str] = &["code", "docs", "shell"];
+
+/// Extensions of files whose edits count as `docs` rather than `code`.
+const DOC_EXTENSIONS: &[&str] = &["md", "markdown", "mdx", "txt", "rst", "adoc"];
+
+/// Every `{{name}}` placeholder `Analysis::template_vars` fills in.
+pub const TEMPLATE_VARS: &[&str] = &[
+    "user_messages",
+    "tool_turns",
+    "task_calls",
+    "test_runs",
+    "tool_errors",
+    "distinct_tools",
+    "threshold_ratio",
+];
+
+/// Tools counted as `search_calls`.
+const SEARCH_TOOLS: &[&str] = &["Grep", "Glob"];
+
+/// Counts extracted from a transcript.
+#[derive(Default, Debug, Serialize)]
+pub struct Analysis {
+    /// Parsed entries folded in (after `tail_turns`); zero for an empty or
+    /// blank-only transcript.
+    pub entries: usize,
+    pub user_messages: usize,
+    /// Entries of a `turn_entry_types.turn` type, with or without tool use.
+    pub assistant_turns: usize,
+    pub tool_using_turns: usize,
+    /// Tool-using turns with at least one call other than `Task`.
+    pub non_task_turns: usize,
+    /// Every `tool_use` block, however many share a turn.
+    pub tool_calls: usize,
+    /// Individual `Task` tool calls (subagent delegations).
+    pub task_calls: usize,
+    /// `Bash` calls whose command matches a `test_runner_patterns` entry.
+    pub test_runs: usize,
+    /// Calls to a `research_tools` tool (web fetches and searches).
+    pub research_calls: usize,
+    /// Tool results flagged `is_error`.
+    pub tool_errors: usize,
+    /// Characters across every `Bash` command.
+    pub bash_chars_total: usize,
+    /// Characters in the longest single `Bash` command.
+    pub bash_chars_max: usize,
+    /// `Grep` and `Glob` calls, for `max_search_ratio`.
+    pub search_calls: usize,
+    /// Tool calls not in `read_only_tools`.
+    pub mutating_calls: usize,
+    /// Tool calls in `read_only_tools`.
+    pub read_calls: usize,
+    /// Every tool name called at least once.
+    pub tool_names: BTreeSet<String>,
+    /// Mutating calls per `WORK_TYPES` label: edits to source files
+    /// (`code`), to prose files (`docs`), and `Bash` commands (`shell`).
+    /// Memory writes aren't counted.
+    pub work_counts: BTreeMap<&'static str, usize>,
+    /// Non-memory file edits to a `code_extensions` file, and to any other.
+    pub code_edits: usize,
+    pub other_edits: usize,
+    /// Any `Edit` or `Write` to a memory path.
+    pub has_memory_write: bool,
+    /// A `Write` to a memory path — likely a new note rather than a tweak.
+    pub has_memory_full_write: bool,
+    /// Lines skipped for exceeding `max_line_bytes`.
+    pub skipped_oversize: usize,
+    /// Non-blank lines skipped because they aren't valid JSON (including
+    /// nesting beyond serde_json's recursion limit).
+    pub skipped_malformed: usize,
+    /// Every write that satisfied the memory check, in order. Under
+    /// `penalize_memory_deletion`, minus those whose file was then deleted
+    /// and those that only wrote a deleted file back.
+    pub memory_writes: Vec<MemoryWrite>,
+    /// Memory-path files the session deleted or moved out of the memory
+    /// paths, and didn't write back, in order. A file the session created
+    /// itself isn't counted.
+    pub memory_deletions: Vec<String>,
+    /// `user_messages` as of the most recent memory write.
+    pub user_messages_at_last_write: Option<usize>,
+    /// `assistant_turns` as of the most recent memory write, counting the
+    /// turn that made it.
+    pub turns_at_last_write: Option<usize>,
+    /// First `cwd` recorded on an entry, for replaying saved transcripts.
+    pub cwd: Option<String>,
+    /// First parseable entry `timestamp` (seconds since the epoch), for
+    /// `replay --group-by day`.
+    pub started_at: Option<i64>,
+    /// FNV-1a hash of the raw transcript bytes.
+    pub content_hash: u64,
+    /// Last `[[session-reflect:allow|block]]` sentinel in a user message.
+    pub sentinel: Option<Sentinel>,
+    /// The latest user message contains one of `decline_phrases`.
+    pub declined: bool,
+    /// Compaction markers seen; with `reset_on_compaction`, everything
+    /// above counts only what followed the last one.
+    pub compactions: usize,
+}
+
+/// A tool call credited as a memory write.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct MemoryWrite {
+    pub tool: String,
+    /// The file written; `None` for a `capture_tools` call.
+    pub path: Option<String>,
+    /// A new file rather than an edit to one.
+    pub creates: bool,
+}
+
+/// A manual override typed into the conversation.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sentinel {
+    /// `[[session-reflect:allow]]`: never block this session.
+    Allow,
+    /// `[[session-reflect:block]]`: block regardless of thresholds.
+    Block,
+}
+
+const SENTINELS: &[(&str, Sentinel)] = &[
+    ("[[session-reflect:allow]]", Sentinel::Allow),
+    ("[[session-reflect:block]]", Sentinel::Block),
+];
+
+impl Analysis {
+    /// Whether the session's memory writes satisfy the capture check.
+    pub fn captured(&self, config: &Config) -> bool {
+        let written = if config.require_write_not_edit {
+            self.has_memory_full_write
+        } else {
+            self.has_memory_write
+        };
+        let net_deletion = config.penalize_memory_deletion
+            && self.memory_deletions.len() > self.memory_writes.len();
+        written && !net_deletion && !self.memory_is_stale(config)
+    }
+
+    /// More than `memory_staleness` user messages since the last memory
+    /// write, or that write fell outside the last `memory_recency_turns`.
+    fn memory_is_stale(&self, config: &Config) -> bool {
+        let messages = match (config.memory_staleness, self.user_messages_at_last_write) {
+            (Some(limit), Some(at)) => self.user_messages - at > limit,
+            _ => false,
+        };
+        let turns = match (config.memory_recency_turns, self.turns_at_last_write) {
+            (Some(recent), Some(at)) => self.assistant_turns - at >= recent,
+            _ => false,
+        };
+        messages || turns
+    }
+
+    /// The assistant never replied — a session started and aborted, with
+    /// nothing to reflect on.
+    pub fn aborted(&self) -> bool {
+        self.assistant_turns == 0
+    }
+
+    /// Tool-using turns with a call other than `Task` (or those calls,
+    /// under `count_tool_calls_not_turns`), plus `task_weight` for each
+    /// `Task` call, so a weight of 0 leaves delegations out.
+    pub fn effective_tool_turns(&self, config: &Config) -> usize {
+        let base = if config.count_tool_calls_not_turns {
+            self.tool_calls - self.task_calls
+        } else {
+            self.non_task_turns
+        };
+        base + self.task_calls * config.task_weight
+    }
+
+    /// Fraction of tool calls that were searches; zero with no tool calls.
+    pub fn search_ratio(&self) -> f64 {
+        if self.tool_calls == 0 {
+            return 0.0;
+        }
+        self.search_calls as f64 / self.tool_calls as f64
+    }
+
+    /// The label with the most mutating calls, if there were any.
+    pub fn work_type(&self) -> Option<&'static str> {
+        let mut dominant = None;
+        let mut most = 0;
+        for &label in WORK_TYPES {
+            let count = self.work_counts.get(label).copied().unwrap_or(0);
+            if count > most {
+                dominant = Some(label);
+                most = count;
+            }
+        }
+        dominant
+    }
+
+    /// Mutating calls per read call: above 1, mostly changing things. A
+    /// session that changed things without reading any is infinitely so.
+    pub fn edit_intensity(&self) -> f64 {
+        match (self.mutating_calls, self.read_calls) {
+            (0, _) => 0.0,
+            (_, 0) => f64::INFINITY,
+            (mutating, read) => mutating as f64 / read as f64,
+        }
+    }
+
+    /// Whether the session reaches `min_edit_intensity`, if one is set.
+    pub fn edit_heavy(&self, config: &Config) -> bool {
+        config
+            .min_edit_intensity
+            .is_none_or(|min| self.edit_intensity() >= min)
+    }
+
+    /// The work type for pattern selection: `work_type`, unless the session
+    /// falls short of `min_edit_intensity`.
+    pub fn pattern_work_type(&self, config: &Config) -> Option<&'static str> {
+        self.work_type().filter(|_| self.edit_heavy(config))
+    }
+
+    /// Every file edited was source code, per `code_extensions`.
+    pub fn code_only(&self) -> bool {
+        self.code_edits > 0 && self.other_edits == 0
+    }
+
+    /// How many different tools the session used.
+    pub fn distinct_tools(&self) -> usize {
+        self.tool_names.len()
+    }
+
+    /// `user_weight * user_messages + tool_weight * tool_turns`, compared
+    /// against `score_threshold` in score mode.
+    pub fn score(&self, config: &Config) -> f64 {
+        config.user_weight * self.user_messages as f64
+            + config.tool_weight * self.effective_tool_turns(config) as f64
+    }
+
+    /// Effective tool-turns as a fraction of `tool_turn_threshold`.
+    pub fn threshold_ratio(&self, config: &Config) -> f64 {
+        self.effective_tool_turns(config) as f64 / config.tool_turn_threshold.max(1) as f64
+    }
+
+    /// Values for the `{{name}}` placeholders in the reflection pattern.
+    pub fn template_vars(&self, config: &Config) -> Vec<(&'static str, String)> {
+        vec![
+            ("user_messages", self.user_messages.to_string()),
+            ("tool_turns", self.effective_tool_turns(config).to_string()),
+            ("task_calls", self.task_calls.to_string()),
+            ("test_runs", self.test_runs.to_string()),
+            ("tool_errors", self.tool_errors.to_string()),
+            ("distinct_tools", self.distinct_tools().to_string()),
+            (
+                "threshold_ratio",
+                format!("{:.0}%", self.threshold_ratio(config) * 100.0),
+            ),
+        ]
+    }
+
+    /// Stand-ins for `template_vars` when there's no transcript to count,
+    /// so the prompt never shows a raw `{{name}}`.
+    pub fn unknown_template_vars() -> Vec<(&'static str, String)> {
+        TEMPLATE_VARS
+            .iter()
+            .map(|&name| {
+                let value = match name {
+                    "threshold_ratio" => "an unknown share",
+                    _ => "an unknown number of",
+                };
+                (name, value.to_string())
+            })
+            .collect()
+    }
+}
+
+/// Open a transcript for analysis. The path is canonicalized up front so a
+/// symlinked transcript is sized and read as its real target. With
+/// `max_transcript_bytes`, only the tail of a larger file is read, starting
+/// at its first complete line.
+pub fn open_transcript(path: &Path, config: &Config) -> std::io::Result<BufReader<File>> {
+    let mut file = File::open(fs::canonicalize(path)?)?;
+    let len = file.metadata()?.len();
+
+    match config.max_transcript_bytes {
+        Some(cap) if len > cap => {
+            // Start one byte early: if that byte ends a line, only it is dropped.
+            file.seek(SeekFrom::Start(len - cap - 1))?;
+            let mut reader = BufReader::new(file);
+            reader.read_until(b'\n', &mut Vec::new())?;
+            Ok(reader)
+        }
+        _ => Ok(BufReader::new(file)),
+    }
+}
+
+/// Analyze transcript for user messages, tool-using turns, and memory writes.
+/// JSONL is read line by line so memory stays bounded by `max_line_bytes`;
+/// a transcript exported as a single JSON array is parsed whole instead.
+pub fn analyze_transcript<R: BufRead>(reader: R, config: &Config) -> Analysis {
+    analyze_session(reader, None, config)
+}
+
+/// `analyze_transcript` for a file of concatenated logs: with a
+/// `session_id`, entries whose `sessionId` names another session are
+/// skipped. Entries without one (e.g. summaries) still count.
+pub fn analyze_session<R: BufRead>(
+    reader: R,
+    session_id: Option<&str>,
+    config: &Config,
+) -> Analysis {
+    let mut reader = HashingReader::new(reader);
+    let mut analysis = Analysis::default();
+    // With `tail_turns`, entries are held in a ring buffer and folded in at the end.
+    let mut tail: VecDeque<Value> = VecDeque::new();
+
+    let mut fold = |analysis: &mut Analysis, entry: Value| {
+        if !within_since(&entry, config) || !in_session(&entry, session_id) {
+            return;
+        }
+        match config.tail_turns {
+            Some(limit) => {
+                if tail.len() == limit {
+                    tail.pop_front();
+                }
+                if limit > 0 {
+                    tail.push_back(entry);
+                }
+            }
+            None => record_entry(analysis, &entry, config),
+        }
+    };
+
+    if starts_with_array(&mut reader) {
+        let mut text = String::new();
+        if reader.read_to_string(&mut text).is_ok() {
+            if let Ok(Value::Array(entries)) = serde_json::from_str(&text) {
+                for entry in entries {
+                    fold(&mut analysis, entry);
+                }
+            }
+        }
+    } else {
+        for line in LineReader::new(&mut reader, config.max_line_bytes) {
+            let line = match line {
+                Line::Text(text) => text,
+                Line::Oversize => {
+                    analysis.skipped_oversize += 1;
+                    continue;
+                }
+            };
+
+            // serde_json's recursion limit (128) turns pathologically nested
+            // lines into a parse error here rather than a stack overflow.
+            let entry: serde_json::Value = match serde_json::from_str(&line) {
+                Ok(v) => v,
+                Err(_) => {
+                    if !line.trim().is_empty() {
+                        analysis.skipped_malformed += 1;
+                    }
+                    continue;
+                }
+            };
+
+            fold(&mut analysis, entry);
+        }
+    }
+
+    for entry in &tail {
+        record_entry(&mut analysis, entry, config);
+    }
+
+    analysis.content_hash = reader.hash;
+    analysis
+}
+
+/// Whether `entry` belongs to `session_id`, or doesn't say which session
+/// it's from.
+fn in_session(entry: &Value, session_id: Option<&str>) -> bool {
+    match (session_id, entry.get("sessionId").and_then(|s| s.as_str())) {
+        (Some(want), Some(id)) => id == want,
+        _ => true,
+    }
+}
+
+/// Whether `entry` falls inside `config.since`, judged by its `timestamp`.
+fn within_since(entry: &Value, config: &Config) -> bool {
+    let since = match &config.since {
+        Some(since) => since,
+        None => return true,
+    };
+    match entry
+        .get("timestamp")
+        .and_then(|t| t.as_str())
+        .and_then(parse_rfc3339)
+    {
+        Some(at) => at >= since.at,
+        None => since.keep_untimestamped,
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a of `bytes`, the same hash `content_hash` uses.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_extend(FNV_OFFSET, bytes)
+}
+
+/// `hash` carried on over `bytes`.
+fn fnv1a_extend(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Passes reads through while hashing every byte consumed (FNV-1a), so the
+/// content hash costs no second pass over the file.
+struct HashingReader<R> {
+    inner: R,
+    hash: u64,
+}
+
+impl<R> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        HashingReader {
+            inner,
+            hash: FNV_OFFSET,
+        }
+    }
+}
+
+impl<R: BufRead> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hash = fnv1a_extend(self.hash, &buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for HashingReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // Already buffered, so this doesn't touch the underlying reader.
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.hash = fnv1a_extend(self.hash, &buf[..amt.min(buf.len())]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+/// Skip leading whitespace and report whether the content opens with `[`.
+fn starts_with_array<R: BufRead>(reader: &mut R) -> bool {
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(b) if !b.is_empty() => b,
+            _ => return false,
+        };
+        match available.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(i) => {
+                let is_array = available[i] == b'[';
+                reader.consume(i);
+                return is_array;
+            }
+            None => {
+                let len = available.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// A `compact_boundary` system entry, or the summary message that follows it.
+fn is_compaction_marker(entry: &Value) -> bool {
+    entry.get("subtype").and_then(|s| s.as_str()) == Some("compact_boundary")
+        || entry.get("isCompactSummary").and_then(|s| s.as_bool()) == Some(true)
+}
+
+/// Entries that recap the conversation rather than take part in it, and so
+/// never count as a user message or turn, whatever `turn_entry_types` says:
+/// - `"subtype": "compact_boundary"`, the marker compaction leaves;
+/// - `"isCompactSummary": true`, the summary that follows it, sent as a
+///   `user` entry;
+/// - `"type": "summary"`, the session title line at the top of a transcript.
+fn is_summary_entry(entry: &Value) -> bool {
+    is_compaction_marker(entry) || entry.get("type").and_then(|t| t.as_str()) == Some("summary")
+}
+
+/// Fold one transcript entry into the running analysis.
+fn record_entry(analysis: &mut Analysis, entry: &Value, config: &Config) {
+    analysis.entries += 1;
+    let types = &config.turn_entry_types;
+    if analysis.cwd.is_none() {
+        analysis.cwd = entry.get("cwd").and_then(|c| c.as_str()).map(String::from);
+    }
+    if analysis.started_at.is_none() {
+        let timestamp = entry.get("timestamp").and_then(|t| t.as_str());
+        analysis.started_at = timestamp.and_then(parse_rfc3339);
+    }
+
+    if is_compaction_marker(entry) {
+        analysis.compactions += 1;
+        if config.reset_on_compaction {
+            // Start over, keeping only what describes the file as a whole
+            *analysis = Analysis {
+                cwd: analysis.cwd.take(),
+                started_at: analysis.started_at,
+                skipped_oversize: analysis.skipped_oversize,
+                entries: analysis.entries,
+                skipped_malformed: analysis.skipped_malformed,
+                compactions: analysis.compactions,
+                ..Analysis::default()
+            };
+            return;
+        }
+    }
+    if is_summary_entry(entry) {
+        return;
+    }
+
+    let entry_type = entry.get("type").and_then(|v| v.as_str()).unwrap_or("");
+    // `content: null` (or `message: null`) is no content, same as a missing
+    // field: the entry still counts as a turn or message, just not a tool use
+    let content = entry
+        .get("message")
+        .and_then(|m| m.get("content"))
+        .filter(|c| !c.is_null())
+        .and_then(|c| c.as_array());
+
+    analysis.tool_errors += content
+        .into_iter()
+        .flatten()
+        .filter(|item| item.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+        .filter(|item| item.get("is_error").and_then(|e| e.as_bool()) == Some(true))
+        .count();
+
+    if types.user.iter().any(|t| t == entry_type) {
+        // Tool output comes back as a `user` entry; it isn't a message.
+        if !content.is_some_and(|items| is_tool_results(items)) {
+            // Greetings and empty sends fall below `min_user_message_chars`
+            let chars = || {
+                message_texts(entry)
+                    .iter()
+                    .map(|text| text.trim().chars().count())
+                    .sum::<usize>()
+            };
+            if config.min_user_message_chars == 0 || chars() >= config.min_user_message_chars {
+                analysis.user_messages += 1;
+            }
+            if let Some(sentinel) = find_sentinel(entry) {
+                analysis.sentinel = Some(sentinel);
+            }
+            analysis.declined = declines_reflection(entry, config);
+        }
+        return;
+    }
+
+    if !types.turn.iter().any(|t| t == entry_type) {
+        return;
+    }
+    analysis.assistant_turns += 1;
+
+    let content = match content {
+        Some(arr) => arr,
+        None => return,
+    };
+
+    let mut turn_has_tool_use = false;
+    let mut turn_has_non_task = false;
+
+    for item in content {
+        let item_type = match item.get("type").and_then(|t| t.as_str()) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        if item_type != "tool_use" {
+            continue;
+        }
+
+        turn_has_tool_use = true;
+        analysis.tool_calls += 1;
+
+        let tool_name = item.get("name").and_then(|n| n.as_str()).unwrap_or("");
+        if tool_name == "Task" {
+            analysis.task_calls += 1;
+        } else {
+            turn_has_non_task = true;
+        }
+        if config.research_tools.iter().any(|t| t == tool_name) {
+            analysis.research_calls += 1;
+        }
+        if SEARCH_TOOLS.contains(&tool_name) {
+            analysis.search_calls += 1;
+        }
+        if config.read_only_tools.iter().any(|t| t == tool_name) {
+            analysis.read_calls += 1;
+        } else {
+            analysis.mutating_calls += 1;
+        }
+        if !analysis.tool_names.contains(tool_name) {
+            analysis.tool_names.insert(tool_name.to_string());
+        }
+        // Partial entries may lack `input` or carry `null`; still a tool-use turn.
+        let input = item.get("input").filter(|i| !i.is_null());
+
+        if tool_name == "Bash" {
+            let command = input
+                .and_then(|i| i.get("command"))
+                .and_then(|c| c.as_str());
+            let chars = command.map_or(0, |c| c.chars().count());
+            analysis.bash_chars_total += chars;
+            analysis.bash_chars_max = analysis.bash_chars_max.max(chars);
+            *analysis.work_counts.entry("shell").or_default() += 1;
+            if command.is_some_and(|c| {
+                config
+                    .test_runner_patterns
+                    .iter()
+                    .any(|p| c.contains(p.as_str()))
+            }) {
+                analysis.test_runs += 1;
+            }
+        }
+
+        // (path, creates the file) for each write this call performed
+        let targets: Vec<(String, bool)> = match tool_name {
+            "Edit" | "Write" => input
+                .and_then(|i| i.get("file_path"))
+                .and_then(|p| p.as_str())
+                .map(|p| vec![(percent_decode(p), tool_name == "Write")])
+                .unwrap_or_default(),
+            "Bash" => input
+                .and_then(|i| i.get("command"))
+                .and_then(|c| c.as_str())
+                .map(|c| file_moves(c).into_iter().map(|m| (m.dest, true)).collect())
+                .unwrap_or_default(),
+            _ => PATCH_FIELDS
+                .iter()
+                .filter_map(|field| input.and_then(|i| i.get(*field)).and_then(|p| p.as_str()))
+                .flat_map(patch_targets)
+                .collect(),
+        };
+
+        // Text an `Edit`/`Write` carries inline, so `min_memory_chars` needn't read the file
+        let inline = match tool_name {
+            "Edit" | "Write" => INLINE_CONTENT_FIELDS
+                .iter()
+                .find_map(|field| input.and_then(|i| i.get(*field)).and_then(|c| c.as_str())),
+            _ => None,
+        };
+
+        // A dedicated capture tool records a learning wherever it stores it
+        if config.capture_tools.iter().any(|t| t == tool_name) {
+            record_memory_write(analysis, tool_name, None, true);
+        }
+
+        let deleted: Vec<String> = match tool_name {
+            // A memory file moved out from under the memory root is as gone
+            // as one deleted
+            "Bash" => input
+                .and_then(|i| i.get("command"))
+                .and_then(|c| c.as_str())
+                .map(|c| {
+                    let moved_out = file_moves(c)
+                        .into_iter()
+                        .filter(|m| m.removes && !is_memory_path(&m.dest, analysis, config))
+                        .map(|m| m.source);
+                    delete_targets(c).into_iter().chain(moved_out).collect()
+                })
+                .unwrap_or_default(),
+            "Edit" | "Write" => Vec::new(),
+            _ => PATCH_FIELDS
+                .iter()
+                .filter_map(|field| input.and_then(|i| i.get(*field)).and_then(|p| p.as_str()))
+                .flat_map(patch_deletions)
+                .collect(),
+        };
+        for file_path in deleted {
+            if is_memory_path(&file_path, analysis, config) {
+                record_memory_deletion(analysis, file_path, config);
+            }
+        }
+
+        for (file_path, creates) in targets {
+            if (is_memory_path(&file_path, analysis, config)
+                || is_tagged_note(&file_path, analysis, config)
+                || is_capture_file(&file_path, analysis, config)
+                || config
+                    .capture_rules
+                    .iter()
+                    .any(|rule| matches_capture_rule(rule, &file_path, inline, analysis, config)))
+                && has_enough_content(&file_path, inline, analysis, config)
+            {
+                // Writing back a file deleted this session only undoes the deletion
+                if restore_memory_deletion(analysis, &file_path) && config.penalize_memory_deletion
+                {
+                    continue;
+                }
+                record_memory_write(analysis, tool_name, Some(file_path), creates);
+            } else {
+                *analysis
+                    .work_counts
+                    .entry(edit_work_type(&file_path))
+                    .or_default() += 1;
+                let extension = Path::new(&file_path).extension().and_then(|e| e.to_str());
+                if extension.is_some_and(|ext| config.code_extensions.iter().any(|c| c == ext)) {
+                    analysis.code_edits += 1;
+                } else {
+                    analysis.other_edits += 1;
+                }
+            }
+        }
+    }
+
+    if turn_has_tool_use {
+        analysis.tool_using_turns += 1;
+    }
+    if turn_has_non_task {
+        analysis.non_task_turns += 1;
+    }
+}
+
+fn record_memory_write(analysis: &mut Analysis, tool: &str, path: Option<String>, creates: bool) {
+    analysis.memory_writes.push(MemoryWrite {
+        tool: tool.to_string(),
+        path,
+        creates,
+    });
+    analysis.has_memory_write = true;
+    analysis.user_messages_at_last_write = Some(analysis.user_messages);
+    analysis.turns_at_last_write = Some(analysis.assistant_turns);
+    if creates {
+        analysis.has_memory_full_write = true;
+    }
+}
+
+/// Note a deleted memory file; under `penalize_memory_deletion` earlier
+/// writes to it stop counting. A file the session itself created nets out
+/// to nothing rather than counting as a deletion too.
+fn record_memory_deletion(analysis: &mut Analysis, path: String, config: &Config) {
+    let deleted = path.trim_start_matches("./");
+    let same = |written: &str| written.trim_start_matches("./") == deleted;
+    let created = analysis
+        .memory_writes
+        .iter()
+        .any(|w| w.creates && w.path.as_deref().is_some_and(same));
+    if config.penalize_memory_deletion {
+        analysis
+            .memory_writes
+            .retain(|w| !w.path.as_deref().is_some_and(same));
+        analysis.has_memory_write = !analysis.memory_writes.is_empty();
+        analysis.has_memory_full_write = analysis.memory_writes.iter().any(|w| w.creates);
+    }
+    if !created && !analysis.memory_deletions.iter().any(|d| same(d)) {
+        analysis.memory_deletions.push(path);
+    }
+}
+
+/// Drop `path` from `memory_deletions`, reporting whether it was there.
+fn restore_memory_deletion(analysis: &mut Analysis, path: &str) -> bool {
+    let path = path.trim_start_matches("./");
+    let before = analysis.memory_deletions.len();
+    analysis
+        .memory_deletions
+        .retain(|d| d.trim_start_matches("./") != path);
+    analysis.memory_deletions.len() < before
+}
+
+/// With `min_memory_chars`, whether the write left enough text behind:
+/// judged from `inline` content when the tool input carried it, else from
+/// the file as it is on disk now (relative to the transcript's `cwd`).
+fn has_enough_content(
+    path: &str,
+    inline: Option<&str>,
+    analysis: &Analysis,
+    config: &Config,
+) -> bool {
+    let min = match config.min_memory_chars {
+        Some(min) => min,
+        None => return true,
+    };
+    let on_disk;
+    let text = match inline {
+        Some(text) => text,
+        None => {
+            let path = match &analysis.cwd {
+                Some(cwd) => Path::new(cwd).join(path),
+                None => Path::new(path).to_path_buf(),
+            };
+            on_disk = read_text(&path).unwrap_or_default();
+            &on_disk
+        }
+    };
+    text.trim().chars().count() >= min
+}
+
+/// With `capture_by_tag`, whether `path` is a Markdown note whose
+/// frontmatter (as it is on disk now) carries one of the configured tags.
+/// Relative paths resolve against the transcript's recorded `cwd`.
+fn is_tagged_note(path: &str, analysis: &Analysis, config: &Config) -> bool {
+    if config.capture_by_tag.is_empty() || !path.ends_with(".md") {
+        return false;
+    }
+    let path = match &analysis.cwd {
+        Some(cwd) => Path::new(cwd).join(path),
+        None => Path::new(path).to_path_buf(),
+    };
+    read_text(&path).is_some_and(|content| {
+        frontmatter_tags(&content)
+            .iter()
+            .any(|tag| config.capture_by_tag.contains(tag))
+    })
+}
+
+/// Whether `path` falls under one of `MEMORY_PATHS`, whichever separator
+/// style the tool recorded it with. With a discovered `memory_root`, only
+/// that vault's `Memory/` counts.
+fn is_memory_path(path: &str, analysis: &Analysis, config: &Config) -> bool {
+    is_under(path, MEMORY_PATHS, analysis, config)
+}
+
+/// Whether `path` falls under one of `prefixes`, as `is_memory_path` judges it.
+fn is_under(path: &str, prefixes: &[&str], analysis: &Analysis, config: &Config) -> bool {
+    let path = path.replace('\\', "/");
+    let root = match &config.memory_root {
+        Some(root) => root,
+        None => return prefixes.iter().any(|m| path.contains(m)),
+    };
+    let path = match &analysis.cwd {
+        Some(cwd) => Path::new(cwd).join(&path),
+        None => Path::new(&path).to_path_buf(),
+    };
+    let path = fs::canonicalize(&path).unwrap_or_else(|_| normalize_lexically(&path));
+    prefixes.iter().any(|m| path.starts_with(root.join(m)))
+}
+
+/// Whether `path` is one of `capture_files`. They resolve against the
+/// vault root or, failing that, the transcript's `cwd`; with neither, a
+/// relative path must match exactly and an absolute one by its tail.
+fn is_capture_file(path: &str, analysis: &Analysis, config: &Config) -> bool {
+    if config.capture_files.is_empty() {
+        return false;
+    }
+    let path = path.replace('\\', "/");
+    let base = config
+        .memory_root
+        .clone()
+        .or_else(|| analysis.cwd.as_ref().map(PathBuf::from));
+    let resolve =
+        |path: PathBuf| fs::canonicalize(&path).unwrap_or_else(|_| normalize_lexically(&path));
+    config.capture_files.iter().any(|file| match &base {
+        Some(base) => {
+            let written = match &analysis.cwd {
+                Some(cwd) => Path::new(cwd).join(&path),
+                None => base.join(&path),
+            };
+            resolve(written) == resolve(base.join(file))
+        }
+        None => {
+            let file = file.trim_start_matches("./");
+            path == file || path.ends_with(&format!("/{}", file))
+        }
+    })
+}
+
+/// Whether a write to `path` satisfies `rule`: it falls under the rule's
+/// prefix and, when the rule requires text, either the `inline` content or
+/// the file as it is on disk now contains it.
+fn matches_capture_rule(
+    rule: &CaptureRule,
+    path: &str,
+    inline: Option<&str>,
+    analysis: &Analysis,
+    config: &Config,
+) -> bool {
+    if rule.path.is_empty() || !is_under(path, &[rule.path.as_str()], analysis, config) {
+        return false;
+    }
+    let needle = match &rule.contains {
+        Some(needle) => needle,
+        None => return true,
+    };
+    if inline.is_some_and(|text| text.contains(needle.as_str())) {
+        return true;
+    }
+    let path = match &analysis.cwd {
+        Some(cwd) => Path::new(cwd).join(path),
+        None => Path::new(path).to_path_buf(),
+    };
+    read_text(&path).is_some_and(|content| content.contains(needle.as_str()))
+}
+
+/// `path` with `%XX` escapes decoded, as some clients send Obsidian paths
+/// (`My%20Note.md`). Left as given if it has a malformed escape or doesn't
+/// decode to UTF-8.
+fn percent_decode(path: &str) -> String {
+    if !path.contains('%') {
+        return path.to_string();
+    }
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|h| std::str::from_utf8(h).ok());
+            match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                Some(b) => {
+                    out.push(b);
+                    i += 3;
+                    continue;
+                }
+                None => return path.to_string(),
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(out).unwrap_or_else(|_| path.to_string())
+}
+
+/// `path` with `.` and `..` components folded lexically, for files that
+/// no longer exist and so can't be canonicalized.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// The text of a user message, whose content is either a string or a
+/// list of `text` blocks.
+fn message_texts(entry: &Value) -> Vec<&str> {
+    match entry.get("message").and_then(|m| m.get("content")) {
+        Some(Value::String(text)) => vec![text],
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| item.get("text").and_then(|t| t.as_str()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether a user message contains one of `decline_phrases`, ignoring case.
+fn declines_reflection(entry: &Value, config: &Config) -> bool {
+    if config.decline_phrases.is_empty() {
+        return false;
+    }
+    message_texts(entry).iter().any(|text| {
+        let text = text.to_lowercase();
+        config
+            .decline_phrases
+            .iter()
+            .any(|p| !p.is_empty() && text.contains(&p.to_lowercase()))
+    })
+}
+
+/// The last override sentinel in a user message.
+fn find_sentinel(entry: &Value) -> Option<Sentinel> {
+    message_texts(entry)
+        .iter()
+        .flat_map(|text| {
+            SENTINELS.iter().flat_map(move |(marker, sentinel)| {
+                text.match_indices(marker).map(|(i, _)| (i, *sentinel))
+            })
+        })
+        .max_by_key(|(i, _)| *i)
+        .map(|(_, sentinel)| sentinel)
+}
+
+/// Content made up entirely of `tool_result` blocks.
+fn is_tool_results(items: &[Value]) -> bool {
+    !items.is_empty()
+        && items
+            .iter()
+            .all(|item| item.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+}
+
+/// Target files named in a unified diff (`+++ b/path`) or an apply_patch
+/// envelope (`*** Add File: path`, `*** Update File: path`), each flagged
+/// with whether the patch creates the file.
+fn patch_targets(patch: &str) -> Vec<(String, bool)> {
+    let mut targets = Vec::new();
+    let mut from_dev_null = false;
+
+    for line in patch.lines() {
+        if let Some(path) = line.strip_prefix("*** Add File: ") {
+            targets.push((path.trim().to_string(), true));
+        } else if let Some(path) = line.strip_prefix("*** Update File: ") {
+            targets.push((path.trim().to_string(), false));
+        } else if let Some(path) = line.strip_prefix("--- ") {
+            from_dev_null = path.trim() == "/dev/null";
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            let path = path.split('\t').next().unwrap_or("").trim();
+            if path != "/dev/null" {
+                let path = path.strip_prefix("b/").unwrap_or(path);
+                targets.push((path.to_string(), from_dev_null));
+            }
+        }
+    }
+
+    targets
+}
+
+/// Files a patch deletes: `*** Delete File:` sections and unified diffs
+/// whose new side is `/dev/null`.
+fn patch_deletions(patch: &str) -> Vec<String> {
+    let mut deleted = Vec::new();
+    let mut old_path = None;
+
+    for line in patch.lines() {
+        if let Some(path) = line.strip_prefix("*** Delete File: ") {
+            deleted.push(path.trim().to_string());
+        } else if let Some(path) = line.strip_prefix("--- ") {
+            let path = path.split('\t').next().unwrap_or("").trim();
+            old_path = Some(path.strip_prefix("a/").unwrap_or(path).to_string());
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            if path.trim() == "/dev/null" {
+                deleted.extend(old_path.take().filter(|p| p != "/dev/null"));
+            }
+        }
+    }
+
+    deleted
+}
+
+/// Files `rm`, `unlink` and `git rm` in a shell command delete. As with
+/// `file_moves`, only plain invocations are understood.
+fn delete_targets(command: &str) -> Vec<String> {
+    let mut deleted = Vec::new();
+    for words in shell_commands(command) {
+        let args = match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["rm" | "unlink", ..] => &words[1..],
+            ["git", "rm", ..] => &words[2..],
+            _ => continue,
+        };
+        let mut options = true;
+        for arg in args {
+            if options && arg == "--" {
+                options = false;
+            } else if !(options && arg.starts_with('-') && arg.len() > 1) {
+                deleted.push(arg.clone());
+            }
+        }
+    }
+    deleted
+}
+
+/// One file `mv`, `cp` or `git mv` put somewhere.
+struct FileMove {
+    source: String,
+    dest: String,
+    /// The source is gone afterwards: a move rather than a copy.
+    removes: bool,
+}
+
+/// Every file `mv`, `cp` and `git mv` in a shell command put somewhere. A
+/// destination that looks like a directory (trailing `/` or no extension)
+/// gets each source's file name appended. Only plain invocations are
+/// understood; anything built with variables, globs or `find -exec` is
+/// missed.
+fn file_moves(command: &str) -> Vec<FileMove> {
+    let mut moves = Vec::new();
+    for words in shell_commands(command) {
+        let (args, removes) = match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["mv", ..] => (&words[1..], true),
+            ["cp", ..] => (&words[1..], false),
+            ["git", "mv", ..] => (&words[2..], true),
+            _ => continue,
+        };
+
+        let mut target_dir = None;
+        let mut operands = Vec::new();
+        let mut iter = args.iter().map(String::as_str);
+        while let Some(arg) = iter.next() {
+            if arg == "-t" || arg == "--target-directory" {
+                target_dir = iter.next();
+            } else if let Some(dir) = arg.strip_prefix("--target-directory=") {
+                target_dir = Some(dir);
+            } else if !(arg.starts_with('-') && arg.len() > 1) {
+                operands.push(arg);
+            }
+        }
+
+        let (dest, sources, is_dir) = match (target_dir, operands.split_last()) {
+            (Some(dir), _) => (dir, &operands[..], true),
+            (None, Some((dest, sources))) if !sources.is_empty() => {
+                let is_dir = dest.ends_with('/') || Path::new(dest).extension().is_none();
+                (*dest, sources, is_dir)
+            }
+            _ => continue,
+        };
+        for source in sources {
+            let path = if is_dir {
+                let name = Path::new(source).file_name().unwrap_or_default();
+                Path::new(dest).join(name).to_string_lossy().into_owned()
+            } else {
+                dest.to_string()
+            };
+            moves.push(FileMove {
+                source: source.to_string(),
+                dest: path,
+                removes,
+            });
+        }
+    }
+    moves
+}
+
+/// Split a shell command into simple commands (at unquoted `;`, `&`, `|`
+/// and newlines) of unquoted words. Single and double quotes and
+/// backslash escapes are honored; nothing is expanded.
+fn shell_commands(command: &str) -> Vec<Vec<String>> {
+    let mut commands = vec![Vec::new()];
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                w.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => w.extend(chars.next()),
+                        c => w.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            ';' | '&' | '|' | '\n' => {
+                commands.last_mut().unwrap().extend(word.take());
+                commands.push(Vec::new());
+            }
+            c if c.is_whitespace() => commands.last_mut().unwrap().extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    commands.last_mut().unwrap().extend(word);
+    commands.retain(|words| !words.is_empty());
+    commands
+}
+
+/// One transcript line, or a marker for a line longer than the cap.
+enum Line {
+    Text(String),
+    Oversize,
+}
+
+/// Splits input on `\n` or `\r` (covering CRLF and bare-CR files) without
+/// ever buffering more than `cap` bytes of a single line.
+struct LineReader<R> {
+    inner: R,
+    cap: usize,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> LineReader<R> {
+    fn new(inner: R, cap: usize) -> Self {
+        LineReader {
+            inner,
+            cap,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LineReader<R> {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        self.buf.clear();
+        let mut oversize = false;
+        let mut read_any = false;
+
+        loop {
+            let available = self.inner.fill_buf().ok()?;
+            if available.is_empty() {
+                if !read_any {
+                    return None;
+                }
+                break;
+            }
+            read_any = true;
+
+            let end = available.iter().position(|&b| b == b'\n' || b == b'\r');
+            let chunk = &available[..end.unwrap_or(available.len())];
+            if !oversize {
+                if self.buf.len() + chunk.len() > self.cap {
+                    oversize = true;
+                    self.buf = Vec::new();
+                } else {
+                    self.buf.extend_from_slice(chunk);
+                }
+            }
+
+            let consumed = end.map_or(available.len(), |e| e + 1);
+            self.inner.consume(consumed);
+            if end.is_some() {
+                break;
+            }
+        }
+
+        Some(if oversize {
+            Line::Oversize
+        } else {
+            Line::Text(String::from_utf8_lossy(&self.buf).into_owned())
+        })
+    }
+}
+
+/// `docs` for prose files, `code` for everything else.
+fn edit_work_type(file_path: &str) -> &'static str {
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+    if DOC_EXTENSIONS
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(extension))
+    {
+        "docs"
+    } else {
+        "code"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Since, TurnEntryTypes};
+    use crate::test_support::*;
+
+    #[test]
+    fn test_crlf_transcript() {
+        let transcript = format!(
+            "{}\r\n{}\r{}\r\n",
+            r#"{"type":"human"}"#,
+            memory_write_turn("Write", "Memory/Learnings/x.md"),
+            tool_turn("Bash"),
+        );
+        let analysis = analyze(&transcript);
+        assert_eq!(analysis.user_messages, 1);
+        assert_eq!(analysis.tool_using_turns, 2);
+        assert!(analysis.has_memory_write);
+    }
+
+    #[test]
+    fn test_template_vars_cover_every_placeholder() {
+        let names = |vars: Vec<(&'static str, String)>| {
+            vars.into_iter().map(|(n, _)| n).collect::<Vec<_>>()
+        };
+        let known = names(analyze("").template_vars(&Config::default()));
+        assert_eq!(known, TEMPLATE_VARS);
+        assert_eq!(names(Analysis::unknown_template_vars()), TEMPLATE_VARS);
+    }
+
+    #[test]
+    fn test_task_weight_scales_effective_turns() {
+        let transcript = [tool_turn("Bash"), tool_turn("Task"), tool_turn("Task")].join("\n");
+        let analysis = analyze(&transcript);
+        assert_eq!(analysis.tool_using_turns, 3);
+        assert_eq!(analysis.task_calls, 2);
+
+        let default = Config::default();
+        assert_eq!(analysis.effective_tool_turns(&default), 3);
+
+        let weighted = Config {
+            task_weight: 4,
+            ..Config::default()
+        };
+        assert_eq!(analysis.effective_tool_turns(&weighted), 9);
+
+        let discounted = Config {
+            task_weight: 0,
+            ..Config::default()
+        };
+        assert_eq!(analysis.effective_tool_turns(&discounted), 1);
+
+        // Parallel Tasks in one turn, and a Task sharing a turn with an Edit
+        let turn = |names: &[&str]| {
+            let items: Vec<_> = names
+                .iter()
+                .map(|name| serde_json::json!({"type": "tool_use", "name": name, "input": {}}))
+                .collect();
+            serde_json::json!({"type": "assistant", "message": {"content": items}}).to_string()
+        };
+        let mut lines = vec![tool_turn("Edit"); 5];
+        lines.push(turn(&["Task", "Task", "Task"]));
+        let parallel = analyze(&lines.join("\n"));
+        assert_eq!(parallel.effective_tool_turns(&discounted), 5);
+        let doubled = Config {
+            task_weight: 2,
+            ..Config::default()
+        };
+        assert_eq!(parallel.effective_tool_turns(&doubled), 11);
+
+        let shared = analyze(&turn(&["Edit", "Task"]));
+        assert_eq!(shared.effective_tool_turns(&discounted), 1);
+        assert_eq!(shared.effective_tool_turns(&default), 2);
+    }
+
+    #[test]
+    fn test_count_tool_calls_not_turns() {
+        let transcript = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Read","input":{}},{"type":"text","text":"next"},{"type":"tool_use","name":"Grep","input":{}},{"type":"tool_use","name":"Bash","input":{"command":"ls"}}]}}"#;
+        let analysis = analyze(transcript);
+        assert_eq!(analysis.tool_calls, 3);
+        assert_eq!(analysis.effective_tool_turns(&Config::default()), 1);
+
+        let calls = Config {
+            count_tool_calls_not_turns: true,
+            ..Config::default()
+        };
+        assert_eq!(analysis.effective_tool_turns(&calls), 3);
+    }
+
+    #[test]
+    fn test_require_write_not_edit_ignores_edits() {
+        let transcript = memory_write_turn("Edit", "Memory/Learnings/x.md");
+        let analysis = analyze(&transcript);
+        assert!(analysis.captured(&Config::default()));
+
+        let strict = Config {
+            require_write_not_edit: true,
+            ..Config::default()
+        };
+        assert!(!analysis.captured(&strict));
+
+        let written = analyze(&memory_write_turn("Write", "Memory/Decisions/y.md"));
+        assert!(written.captured(&strict));
+    }
+
+    #[test]
+    fn test_oversize_line_skipped() {
+        let huge = format!(r#"{{"type":"human","blob":"{}"}}"#, "A".repeat(10_000));
+        let transcript = [huge, tool_turn("Bash"), r#"{"type":"human"}"#.to_string()].join("\n");
+        let config = Config {
+            max_line_bytes: 1024,
+            ..Config::default()
+        };
+        let analysis = analyze_transcript(transcript.as_bytes(), &config);
+        assert_eq!(analysis.skipped_oversize, 1);
+        assert_eq!(analysis.user_messages, 1);
+        assert_eq!(analysis.tool_using_turns, 1);
+    }
+
+    #[test]
+    fn test_tail_turns_limits_analysis() {
+        let mut lines: Vec<String> = (0..5).map(|_| tool_turn("Bash")).collect();
+        lines.push("not json".to_string());
+        lines.push(r#"{"type":"human"}"#.to_string());
+        lines.push(tool_turn("Read"));
+        let transcript = lines.join("\n");
+
+        let config = Config {
+            tail_turns: Some(2),
+            ..Config::default()
+        };
+        let analysis = analyze_transcript(transcript.as_bytes(), &config);
+        assert_eq!(analysis.user_messages, 1);
+        assert_eq!(analysis.tool_using_turns, 1);
+
+        assert_eq!(analyze(&transcript).tool_using_turns, 6);
+    }
+
+    #[test]
+    fn test_patch_tool_memory_write() {
+        let diff = "--- /dev/null\n+++ b/Memory/Learnings/x.md\n@@ -0,0 +1 @@\n+note\n";
+        let analysis = analyze(&patch_turn("patch", diff));
+        assert!(analysis.has_memory_write);
+        assert!(analysis.has_memory_full_write);
+
+        let envelope =
+            "*** Begin Patch\n*** Update File: Memory/Decisions/y.md\n@@\n-a\n+b\n*** End Patch\n";
+        let analysis = analyze(&patch_turn("input", envelope));
+        assert!(analysis.has_memory_write);
+        assert!(!analysis.has_memory_full_write);
+
+        let elsewhere = "--- a/src/main.rs\n+++ b/src/main.rs\n";
+        assert!(!analyze(&patch_turn("diff", elsewhere)).has_memory_write);
+    }
+
+    #[test]
+    fn test_json_array_transcript_matches_jsonl() {
+        let entries = [
+            r#"{"type":"human"}"#.to_string(),
+            tool_turn("Task"),
+            memory_write_turn("Write", "Memory/Learnings/x.md"),
+            r#"{"type":"human"}"#.to_string(),
+        ];
+        let jsonl = analyze(&entries.join("\n"));
+        let array = analyze(&format!("  \n[{}]", entries.join(",\n")));
+
+        assert_eq!(array.user_messages, 2);
+        assert_eq!(array.user_messages, jsonl.user_messages);
+        assert_eq!(array.tool_using_turns, jsonl.tool_using_turns);
+        assert_eq!(array.task_calls, jsonl.task_calls);
+        assert_eq!(array.has_memory_write, jsonl.has_memory_write);
+    }
+
+    #[test]
+    fn test_content_hash_tracks_bytes() {
+        let a = analyze(&tool_turn("Bash"));
+        assert_eq!(a.content_hash, analyze(&tool_turn("Bash")).content_hash);
+        assert_ne!(a.content_hash, analyze(&tool_turn("Read")).content_hash);
+        assert_ne!(a.content_hash, Analysis::default().content_hash);
+    }
+
+    #[test]
+    fn test_custom_turn_entry_types() {
+        let system_turn = tool_turn("Bash").replace(r#""type":"assistant""#, r#""type":"system""#);
+        let tool_result = r#"{"type":"user","message":{"content":[{"type":"tool_result"}]}}"#;
+        let transcript = [
+            r#"{"type":"human"}"#,
+            r#"{"type":"user","message":{"content":"hi"}}"#,
+            tool_result,
+            &tool_turn("Bash"),
+            &system_turn,
+        ]
+        .join("\n");
+
+        let analysis = analyze(&transcript);
+        assert_eq!(analysis.user_messages, 2);
+        assert_eq!(analysis.tool_using_turns, 1);
+
+        let config = Config {
+            turn_entry_types: TurnEntryTypes {
+                turn: vec!["assistant".to_string(), "system".to_string()],
+                user: vec!["human".to_string()],
+            },
+            ..Config::default()
+        };
+        let analysis = analyze_transcript(transcript.as_bytes(), &config);
+        assert_eq!(analysis.user_messages, 1);
+        assert_eq!(analysis.tool_using_turns, 2);
+    }
+
+    #[test]
+    fn test_counts_test_runs() {
+        let transcript = [
+            bash_turn("cargo test"),
+            bash_turn("cd crate && cargo test --workspace"),
+            bash_turn("cargo build"),
+            bash_turn("pytest -q tests/"),
+        ]
+        .join("\n");
+
+        assert_eq!(analyze(&transcript).test_runs, 3);
+    }
+
+    #[test]
+    fn test_sums_bash_command_lengths() {
+        let transcript = [
+            bash_turn("ls"),
+            bash_turn("for f in *.rs; do\n  wc -l \"$f\"\ndone"),
+            bash_turn("écho"),
+            tool_turn("Bash"),
+        ]
+        .join("\n");
+
+        let analysis = analyze(&transcript);
+        assert_eq!(analysis.bash_chars_total, 2 + 35 + 4);
+        assert_eq!(analysis.bash_chars_max, 35);
+    }
+
+    #[test]
+    fn test_edit_intensity_is_mutating_per_read_call() {
+        let transcript = [
+            tool_turn("Read"),
+            tool_turn("Grep"),
+            tool_turn("Read"),
+            tool_turn("Glob"),
+            memory_write_turn("Edit", "src/main.rs"),
+            tool_turn("Bash"),
+        ]
+        .join("\n");
+        let analysis = analyze(&transcript);
+        assert_eq!((analysis.mutating_calls, analysis.read_calls), (2, 4));
+        assert_eq!(analysis.edit_intensity(), 0.5);
+        assert_eq!(analysis.pattern_work_type(&Config::default()), Some("code"));
+
+        let config = |min| Config {
+            min_edit_intensity: Some(min),
+            ..Config::default()
+        };
+        assert!(analysis.edit_heavy(&config(0.5)));
+        assert_eq!(analysis.pattern_work_type(&config(1.0)), None);
+        assert_eq!(analyze(&tool_turn("Read")).edit_intensity(), 0.0);
+        assert_eq!(analyze(&tool_turn("Bash")).edit_intensity(), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_work_type_is_dominant_mutating_category() {
+        let transcript = [
+            memory_write_turn("Edit", "notes/plan.md"),
+            memory_write_turn("Write", "docs/guide.RST"),
+            memory_write_turn("Edit", "src/main.rs"),
+            memory_write_turn("Write", "Memory/Learnings/x.md"),
+            tool_turn("Bash"),
+        ]
+        .join("\n");
+        let analysis = analyze(&transcript);
+        assert_eq!(
+            analysis.work_counts,
+            [("code", 1), ("docs", 2), ("shell", 1)].into()
+        );
+        assert_eq!(analysis.work_type(), Some("docs"));
+        assert_eq!(analyze(&tool_turn("Read")).work_type(), None);
+    }
+
+    #[test]
+    fn test_min_memory_chars_checks_inline_content() {
+        let config = Config {
+            min_memory_chars: Some(20),
+            ..Config::default()
+        };
+        let write = |field: &str, text: &str| {
+            serde_json::json!({"type": "assistant", "message": {"content": [{
+                "type": "tool_use",
+                "name": if field == "content" { "Write" } else { "Edit" },
+                "input": {"file_path": "/nowhere/Memory/Learnings/x.md", field: text},
+            }]}})
+            .to_string()
+        };
+        let captured = |turn: String| analyze_transcript(turn.as_bytes(), &config).has_memory_write;
+
+        assert!(!captured(write("content", "  \n\n ")));
+        assert!(!captured(write("new_string", "TODO")));
+        assert!(captured(write(
+            "content",
+            "Hooks run with a clean environment."
+        )));
+        assert!(captured(write(
+            "new_string",
+            "Prefer the state lock over retries."
+        )));
+        // Nothing inline and nothing on disk
+        assert!(!captured(memory_write_turn(
+            "Write",
+            "/nowhere/Memory/Learnings/x.md"
+        )));
+    }
+
+    #[test]
+    fn test_user_only_transcript_is_aborted() {
+        let analysis = analyze(&[r#"{"type":"human"}"#; 3].join("\n"));
+        assert_eq!(analysis.user_messages, 3);
+        assert!(analysis.aborted());
+        assert!(!analyze(r#"{"type":"assistant","message":{"content":[]}}"#).aborted());
+    }
+
+    #[test]
+    fn test_moves_into_memory_count_as_writes() {
+        let writes = |command: &str| analyze(&bash_turn(command)).has_memory_write;
+
+        assert!(writes("mv notes/x.md Memory/Learnings/x.md"));
+        assert!(writes(
+            "cd vault && git mv -f 'draft one.md' Memory/Decisions/"
+        ));
+        assert!(writes("cp -t Memory/Learnings a.md b.md"));
+        assert!(writes("ls; mv x.md Memory/Learnings"));
+        assert!(!writes("mv Memory/Learnings/x.md notes/x.md"));
+        assert!(!writes("echo mv x.md Memory/Learnings/x.md"));
+        assert!(!writes("mv Memory/Learnings/x.md"));
+    }
+
+    #[test]
+    fn test_memory_deletions_detected_and_not_credited() {
+        let transcript = [
+            memory_write_turn("Write", "Memory/Learnings/a.md"),
+            bash_turn("rm -f -- Memory/Learnings/a.md Memory/Decisions/b.md notes.txt"),
+            patch_turn(
+                "patch",
+                "*** Begin Patch\n*** Delete File: Memory/Learnings/c.md\n*** End Patch",
+            ),
+        ]
+        .join("\n");
+
+        let lenient = analyze(&transcript);
+        assert_eq!(
+            lenient.memory_deletions,
+            ["Memory/Decisions/b.md", "Memory/Learnings/c.md"]
+        );
+        assert!(lenient.captured(&Config::default()));
+
+        let config = Config {
+            penalize_memory_deletion: true,
+            ..Config::default()
+        };
+        let strict = analyze_transcript(transcript.as_bytes(), &config);
+        assert!(strict.memory_writes.is_empty());
+        assert!(!strict.captured(&config));
+
+        let rewritten = [
+            transcript,
+            memory_write_turn("Write", "Memory/Learnings/d.md"),
+        ]
+        .join("\n");
+        let net = analyze_transcript(rewritten.as_bytes(), &config);
+        assert_eq!(net.memory_writes.len(), 1);
+        assert!(!net.captured(&config), "two deleted, one written");
+
+        // A session's own scratch note, written and removed, costs nothing
+        let scratch = [
+            memory_write_turn("Write", "Memory/Learnings/draft.md"),
+            memory_write_turn("Write", "Memory/Learnings/final.md"),
+            bash_turn("rm Memory/Learnings/draft.md Memory/Learnings/old.md"),
+        ]
+        .join("\n");
+        let scratch = analyze_transcript(scratch.as_bytes(), &config);
+        assert_eq!(scratch.memory_deletions, ["Memory/Learnings/old.md"]);
+        assert!(scratch.captured(&config));
+
+        // Moving a note out is a deletion; writing it back undoes one
+        let moved = [
+            bash_turn(
+                "mv Memory/Learnings/x.md /tmp/ && mv Memory/Learnings/y.md Memory/Decisions/",
+            ),
+            bash_turn("cp Memory/Learnings/z.md /tmp/z.md"),
+            bash_turn("rm Memory/Learnings/w.md"),
+            memory_write_turn("Write", "Memory/Learnings/w.md"),
+        ]
+        .join("\n");
+        let moved = analyze_transcript(moved.as_bytes(), &config);
+        assert_eq!(moved.memory_deletions, ["Memory/Learnings/x.md"]);
+        assert_eq!(moved.memory_writes.len(), 1, "y.md moved into Decisions");
+    }
+
+    #[test]
+    fn test_reset_on_compaction_counts_only_later_work() {
+        let boundary = r#"{"type":"system","subtype":"compact_boundary"}"#;
+        let summary =
+            r#"{"type":"user","isCompactSummary":true,"message":{"content":"So far..."}}"#;
+        let mut lines = vec![r#"{"type":"human"}"#.to_string(); 5];
+        lines.push(memory_write_turn("Write", "Memory/Learnings/early.md"));
+        lines.extend([boundary.to_string(), summary.to_string()]);
+        lines.extend([
+            r#"{"type":"human"}"#.to_string(),
+            tool_turn("Bash"),
+            tool_turn("Edit"),
+        ]);
+        let transcript = lines.join("\n");
+
+        let whole = analyze(&transcript);
+        assert_eq!((whole.user_messages, whole.tool_using_turns), (6, 3));
+        assert!(whole.has_memory_write);
+        assert_eq!(whole.compactions, 2);
+
+        let config = Config {
+            reset_on_compaction: true,
+            ..Config::default()
+        };
+        let recent = analyze_transcript(transcript.as_bytes(), &config);
+        assert_eq!((recent.user_messages, recent.tool_using_turns), (1, 2));
+        assert!(!recent.has_memory_write);
+        assert_eq!(recent.compactions, 2);
+    }
+
+    #[test]
+    fn test_summary_entries_are_not_counted() {
+        let recap = "So far: ".to_string() + &"refactored the parser, ".repeat(200);
+        let summary = serde_json::json!({
+            "type": "user",
+            "isCompactSummary": true,
+            "message": {"content": [{"type": "text", "text": recap}]},
+        });
+        let title = r#"{"type":"summary","summary":"Parser refactor","leafUuid":"abc"}"#;
+        let boundary = r#"{"type":"system","subtype":"compact_boundary"}"#;
+        let transcript = [
+            title.to_string(),
+            boundary.to_string(),
+            summary.to_string(),
+            tool_turn("Bash"),
+        ]
+        .join("\n");
+
+        let config = Config {
+            turn_entry_types: TurnEntryTypes {
+                turn: vec!["assistant".into(), "system".into(), "summary".into()],
+                ..TurnEntryTypes::default()
+            },
+            ..Config::default()
+        };
+        for config in [Config::default(), config] {
+            let analysis = analyze_transcript(transcript.as_bytes(), &config);
+            assert_eq!(analysis.entries, 4);
+            assert_eq!(analysis.user_messages, 0);
+            assert_eq!(
+                (analysis.assistant_turns, analysis.tool_using_turns),
+                (1, 1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_and_blank_transcripts_count_nothing() {
+        for transcript in ["", "\n\n", "  \r\n\t\n   "] {
+            let analysis = analyze(transcript);
+            assert_eq!(analysis.entries, 0, "{:?}", transcript);
+            assert_eq!(analysis.user_messages, 0);
+            assert_eq!(analysis.tool_using_turns, 0);
+            assert_eq!(analysis.skipped_malformed, 0);
+            assert!(!analysis.has_memory_write);
+        }
+    }
+
+    #[test]
+    fn test_backslash_memory_path_detected() {
+        let turn = serde_json::json!({"type": "assistant", "message": {"content": [{
+            "type": "tool_use",
+            "name": "Write",
+            "input": {"file_path": r"C:\Users\me\Data\Memory\Learnings\x.md"},
+        }]}});
+        let analysis = analyze(&turn.to_string());
+        assert!(analysis.has_memory_write);
+        assert!(analysis.has_memory_full_write);
+    }
+
+    #[test]
+    fn test_counts_tool_errors() {
+        let results = r#"{"type":"user","message":{"content":[
+            {"type":"tool_result","is_error":true},
+            {"type":"tool_result","is_error":false},
+            {"type":"tool_result"}]}}"#
+            .replace('\n', "");
+        let analysis = analyze(&[results.clone(), results].join("\n"));
+        assert_eq!(analysis.tool_errors, 2);
+        assert_eq!(analysis.user_messages, 0);
+    }
+
+    #[test]
+    fn test_counts_mutating_calls() {
+        let transcript = ["Read", "Grep", "Bash", "Edit", "Glob"]
+            .map(tool_turn)
+            .join("\n");
+        let analysis = analyze(&transcript);
+        assert_eq!(analysis.tool_using_turns, 5);
+        assert_eq!(analysis.mutating_calls, 2);
+    }
+
+    #[test]
+    fn test_min_user_message_chars_skips_trivial_messages() {
+        let blocks = r#"{"type":"user","message":{"content":[{"type":"text","text":"Refactor the parser "},{"type":"text","text":"to stream."}]}}"#;
+        let transcript = [
+            user_message("hi"),
+            user_message("  thanks!  "),
+            user_message(""),
+            user_message("Please add a --since flag"),
+            blocks.to_string(),
+        ]
+        .join("\n");
+        assert_eq!(analyze(&transcript).user_messages, 5);
+
+        let config = Config {
+            min_user_message_chars: 10,
+            ..Config::default()
+        };
+        assert_eq!(
+            analyze_transcript(transcript.as_bytes(), &config).user_messages,
+            2
+        );
+    }
+
+    #[test]
+    fn test_percent_encoded_memory_paths() {
+        let analysis = analyze(&memory_write_turn("Write", "Memory/Learnings/My%20Note.md"));
+        assert!(analysis.has_memory_write);
+        assert_eq!(
+            analysis.memory_writes[0].path.as_deref(),
+            Some("Memory/Learnings/My Note.md")
+        );
+
+        let encoded = memory_write_turn("Edit", "Memory%2FDecisions%2FPick%20serde.md");
+        assert!(analyze(&encoded).has_memory_write);
+
+        assert_eq!(percent_decode("100%.md"), "100%.md");
+        assert_eq!(percent_decode("caf%C3%A9.md"), "café.md");
+        assert_eq!(percent_decode("bad%FF.md"), "bad%FF.md");
+    }
+
+    #[test]
+    fn test_null_content_is_no_content() {
+        let transcript = [
+            r#"{"type":"assistant","message":{"content":null}}"#,
+            r#"{"type":"assistant","message":null}"#,
+            r#"{"type":"user","message":{"content":null}}"#,
+            &tool_turn("Bash"),
+        ]
+        .join("\n");
+        let analysis = analyze(&transcript);
+        assert_eq!(analysis.assistant_turns, 3);
+        assert_eq!(analysis.tool_using_turns, 1);
+        assert_eq!(analysis.user_messages, 1);
+        assert_eq!(analysis.skipped_malformed, 0);
+    }
+
+    #[test]
+    fn test_tool_use_without_input() {
+        let missing =
+            r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Edit"}]}}"#;
+        let null = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"apply_patch","input":null}]}}"#;
+        let analysis = analyze(&[missing, null].join("\n"));
+        assert_eq!(analysis.tool_using_turns, 2);
+        assert_eq!(analysis.mutating_calls, 2);
+        assert!(!analysis.has_memory_write);
+    }
+
+    #[test]
+    fn test_counts_research_calls() {
+        let transcript = ["WebFetch", "WebSearch", "WebFetch", "Read"]
+            .map(tool_turn)
+            .join("\n");
+        assert_eq!(analyze(&transcript).research_calls, 3);
+
+        let config = Config {
+            research_tools: vec!["WebFetch".to_string()],
+            ..Config::default()
+        };
+        assert_eq!(
+            analyze_transcript(transcript.as_bytes(), &config).research_calls,
+            2
+        );
+    }
+
+    #[test]
+    fn test_since_filters_by_timestamp() {
+        let stamped = |line: &str, at: &str| {
+            let mut entry: Value = serde_json::from_str(line).unwrap();
+            entry["timestamp"] = at.into();
+            entry.to_string()
+        };
+        let transcript = [
+            stamped(r#"{"type":"human"}"#, "2026-10-14T09:00:00Z"),
+            stamped(&tool_turn("Bash"), "2026-10-14T09:05:00Z"),
+            stamped(r#"{"type":"human"}"#, "2026-10-14T13:00:00.500Z"),
+            stamped(&tool_turn("Edit"), "2026-10-14T15:01:00+02:00"),
+            tool_turn("Read"),
+        ]
+        .join("\n");
+
+        let whole = analyze(&transcript);
+        assert_eq!((whole.user_messages, whole.tool_using_turns), (2, 3));
+
+        let mut config = Config {
+            since: Some(Since {
+                at: parse_rfc3339("2026-10-14T13:00:00Z").unwrap(),
+                keep_untimestamped: true,
+            }),
+            ..Config::default()
+        };
+        let after = analyze_transcript(transcript.as_bytes(), &config);
+        assert_eq!((after.user_messages, after.tool_using_turns), (1, 2));
+
+        config.since.as_mut().unwrap().keep_untimestamped = false;
+        let after = analyze_transcript(transcript.as_bytes(), &config);
+        assert_eq!((after.user_messages, after.tool_using_turns), (1, 1));
+    }
+
+    #[test]
+    fn test_session_id_scopes_mixed_transcript() {
+        let tagged = |line: &str, id: &str| {
+            let mut entry: Value = serde_json::from_str(line).unwrap();
+            entry["sessionId"] = id.into();
+            entry.to_string()
+        };
+        let transcript = [
+            tagged(r#"{"type":"human"}"#, "old"),
+            tagged(&tool_turn("Bash"), "old"),
+            tagged(&tool_turn("Bash"), "old"),
+            r#"{"type":"summary","summary":"Earlier work"}"#.to_string(),
+            tagged(r#"{"type":"human"}"#, "new"),
+            tagged(&tool_turn("Edit"), "new"),
+        ]
+        .join("\n");
+
+        let whole = analyze(&transcript);
+        assert_eq!((whole.user_messages, whole.tool_using_turns), (2, 3));
+
+        let config = Config::default();
+        let scoped = analyze_session(transcript.as_bytes(), Some("new"), &config);
+        assert_eq!((scoped.user_messages, scoped.tool_using_turns), (1, 1));
+        let entries = analyze_session(transcript.as_bytes(), None, &config).entries;
+        assert_eq!(entries, whole.entries);
+    }
+
+    #[test]
+    fn test_declined_tracks_latest_user_message() {
+        assert!(analyze(&user_message("Skip reflection, please")).declined);
+        assert!(!analyze(&user_message("Keep going")).declined);
+        let later = [
+            user_message("no need to capture"),
+            user_message("actually, one more fix"),
+        ]
+        .join("\n");
+        assert!(!analyze(&later).declined);
+    }
+
+    #[test]
+    fn test_override_sentinels() {
+        let message = |text: &str| {
+            let content = serde_json::json!([{"type": "text", "text": text}]);
+            serde_json::json!({"type": "user", "message": {"content": content}}).to_string()
+        };
+        assert_eq!(analyze(&message("no override here")).sentinel, None);
+        assert_eq!(
+            analyze(&message("skip it [[session-reflect:allow]]")).sentinel,
+            Some(Sentinel::Allow)
+        );
+
+        let transcript = [
+            message("[[session-reflect:allow]]"),
+            r#"{"type":"human","message":{"content":"no, [[session-reflect:block]]"}}"#.to_string(),
+        ]
+        .join("\n");
+        assert_eq!(analyze(&transcript).sentinel, Some(Sentinel::Block));
+    }
+
+    #[test]
+    fn test_counts_distinct_tools() {
+        let transcript = ["Bash", "Edit", "Bash", "Read", "Edit"]
+            .map(tool_turn)
+            .join("\n");
+        let analysis = analyze(&transcript);
+        assert_eq!(analysis.distinct_tools(), 3);
+        assert!(analysis.tool_names.contains("Read"));
+    }
+
+    #[test]
+    fn test_capture_tool_counts_as_memory_write() {
+        let transcript = tool_turn("ReflectCapture");
+        assert!(!analyze(&transcript).has_memory_write);
+
+        let config = Config {
+            capture_tools: vec!["ReflectCapture".to_string()],
+            ..Config::default()
+        };
+        let analysis = analyze_transcript(transcript.as_bytes(), &config);
+        assert!(analysis.captured(&config));
+    }
+
+    #[test]
+    fn test_capture_by_tag() {
+        let vault = TempDir::new("capture-by-tag");
+        let tagged = vault.path().join("Notes/tagged.md");
+        let untagged = vault.path().join("Notes/untagged.md");
+        std::fs::create_dir_all(tagged.parent().unwrap()).unwrap();
+        std::fs::write(&tagged, "---\ntype: learning\n---\nHooks need timeouts.\n").unwrap();
+        std::fs::write(&untagged, "---\ntype: journal\n---\nToday.\n").unwrap();
+
+        let config = Config {
+            capture_by_tag: vec!["learning".to_string(), "decision".to_string()],
+            ..Config::default()
+        };
+        let write = |path: &Path| memory_write_turn("Write", &path.to_string_lossy());
+
+        let analysis = analyze_transcript(write(&tagged).as_bytes(), &config);
+        assert!(analysis.has_memory_write);
+        let analysis = analyze_transcript(write(&untagged).as_bytes(), &config);
+        assert!(!analysis.has_memory_write);
+        assert!(!analyze(&write(&tagged)).has_memory_write);
+    }
+
+    #[test]
+    fn test_capture_rules_require_content() {
+        let config = Config {
+            capture_rules: vec![CaptureRule {
+                path: "Journal/Daily/".to_string(),
+                contains: Some("## Reflection".to_string()),
+            }],
+            ..Config::default()
+        };
+        let write = |path: &str, content: &str| {
+            serde_json::json!({"type": "assistant", "message": {"content": [{
+                "type": "tool_use",
+                "name": "Write",
+                "input": {"file_path": path, "content": content}
+            }]}})
+            .to_string()
+        };
+
+        let reflected = write(
+            "Journal/Daily/2026-10-14.md",
+            "# Tuesday\n\n## Reflection\nSlow CI.\n",
+        );
+        assert!(analyze_transcript(reflected.as_bytes(), &config).has_memory_write);
+        let plain = write("Journal/Daily/2026-10-14.md", "# Tuesday\n\nShipped.\n");
+        assert!(!analyze_transcript(plain.as_bytes(), &config).has_memory_write);
+        let elsewhere = write("Notes/2026-10-14.md", "## Reflection\n");
+        assert!(!analyze_transcript(elsewhere.as_bytes(), &config).has_memory_write);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_size_cap_follows_symlinked_transcript() {
+        let dir = TempDir::new("symlinked-transcript");
+        let tail = ["Bash", "Edit", "Read"].map(tool_turn).join("\n");
+        let humans = [r#"{"type":"human"}"#; 10].join("\n");
+        let real = dir.path().join("real.jsonl");
+        std::fs::write(&real, format!("{}\n{}", humans, tail)).unwrap();
+        let link = dir.path().join("link.jsonl");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        let config = Config {
+            max_transcript_bytes: Some(tail.len() as u64),
+            ..Config::default()
+        };
+        let analysis = analyze_transcript(open_transcript(&link, &config).unwrap(), &config);
+        assert_eq!(analysis.user_messages, 0);
+        assert_eq!(analysis.tool_using_turns, 3);
+
+        let uncapped = Config::default();
+        let analysis = analyze_transcript(open_transcript(&link, &uncapped).unwrap(), &uncapped);
+        assert_eq!(analysis.user_messages, 10);
+    }
+
+    #[test]
+    fn test_memory_root_limits_credited_writes() {
+        let config = Config {
+            memory_root: Some(PathBuf::from("/data/vault")),
+            ..Config::default()
+        };
+        let credited = |path: &str| {
+            analyze_transcript(memory_write_turn("Write", path).as_bytes(), &config)
+                .has_memory_write
+        };
+        assert!(credited("/data/vault/Memory/Learnings/x.md"));
+        assert!(credited(
+            "/data/vault/projects/app/../../Memory/Decisions/y.md"
+        ));
+        assert!(!credited("/data/other/Memory/Learnings/x.md"));
+    }
+
+    #[test]
+    fn test_deeply_nested_line_skipped() {
+        let nested = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        let transcript = [tool_turn("Bash"), nested, String::new(), tool_turn("Edit")].join("\n");
+
+        let analysis = analyze(&transcript);
+        assert_eq!(analysis.skipped_malformed, 1);
+        assert_eq!(analysis.tool_using_turns, 2);
+    }
+
+    #[test]
+    fn test_records_memory_write_attribution() {
+        let transcript = [
+            memory_write_turn("Edit", "Memory/Learnings/a.md"),
+            memory_write_turn("Edit", "src/main.rs"),
+            memory_write_turn("Write", "Memory/Decisions/b.md"),
+        ]
+        .join("\n");
+        let write = |tool: &str, path: &str| MemoryWrite {
+            tool: tool.to_string(),
+            path: Some(path.to_string()),
+            creates: tool == "Write",
+        };
+
+        assert_eq!(
+            analyze(&transcript).memory_writes,
+            [
+                write("Edit", "Memory/Learnings/a.md"),
+                write("Write", "Memory/Decisions/b.md")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_memory_staleness() {
+        let human = r#"{"type":"human"}"#.to_string();
+        let write = memory_write_turn("Write", "Memory/Learnings/x.md");
+        let config = Config {
+            memory_staleness: Some(2),
+            ..Config::default()
+        };
+
+        let fresh = [human.clone(), write.clone(), human.clone(), human.clone()].join("\n");
+        let analysis = analyze(&fresh);
+        assert_eq!(analysis.user_messages_at_last_write, Some(1));
+        assert!(analysis.captured(&config));
+
+        let stale = [write, human.clone(), human.clone(), human].join("\n");
+        let analysis = analyze(&stale);
+        assert!(!analysis.captured(&config));
+        assert!(analysis.captured(&Config::default()));
+    }
+
+    #[test]
+    fn test_memory_recency_turns() {
+        let write = memory_write_turn("Write", "Memory/Learnings/x.md");
+        let config = Config {
+            memory_recency_turns: Some(3),
+            ..Config::default()
+        };
+        let session = |lines: Vec<String>| analyze(&lines.join("\n"));
+
+        let mut early = vec![write.clone()];
+        early.extend((0..49).map(|_| tool_turn("Bash")));
+        let analysis = session(early);
+        assert_eq!(
+            (analysis.turns_at_last_write, analysis.assistant_turns),
+            (Some(1), 50)
+        );
+        assert!(analysis.captured(&Config::default()));
+        assert!(!analysis.captured(&config));
+
+        let mut late: Vec<String> = (0..48).map(|_| tool_turn("Bash")).collect();
+        late.extend([write, tool_turn("Bash"), tool_turn("Bash")]);
+        assert!(session(late).captured(&config));
+    }
+}
@@ -0,0 +1,759 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::analysis::{analyze_transcript, open_transcript, Analysis};
+use crate::clock::{parse_rfc3339, utc_date};
+use crate::config::{Config, Since};
+use crate::decision::{stop_verdict, Outcome};
+use crate::install::{default_settings_path, verify_install, InstallReport, VERSION};
+use crate::pattern::{read_text, strip_frontmatter_and_h1, PATTERN_PATH};
+use crate::{env_var, quiet, warn};
+
+const USAGE: &str = "usage: session-reflect [command] [--format json|text]
+  replay <dir> [--cwd <path>] [--group-by day]
+                                       Stop outcomes over saved transcripts (default: text);
+                                       --group-by day adds per-day capture counts
+  analyze <transcript> [--cwd <path>] [--since <rfc3339> [--exclude-untimestamped]]
+          [--exit-codes]               counts for one transcript, `-` or --stdin to read stdin
+                                       (default: json; also prometheus); --since skips entries
+                                       timestamped before it; --exit-codes exits 2 for a
+                                       substantial session with no memory
+  verify-install [--settings <path>]   compare configured hook binaries' versions (default: text)
+  config [--cwd <path>]                effective merged config and each key's source (default: json)
+  strip-test <dir>                     pattern files whose frontmatter/H1 stripping looks wrong
+                                       (default: text)
+  init-pattern [--cwd <path>] [--force]
+                                       write a starter Session Reflect.md at the pattern path
+                                       (default: text)
+  --version
+  -q, --quiet                          (with any of the above) nothing on stderr
+  (no arguments: read a hook payload from stdin)";
+
+/// Subcommands for working with saved transcripts outside the hook path.
+pub fn run(args: &[String]) -> ExitCode {
+    let (command, default_format) = match args[0].as_str() {
+        "--version" => {
+            println!("session-reflect {}", VERSION);
+            return ExitCode::SUCCESS;
+        }
+        "replay" => (Command::Replay, Format::Text),
+        "analyze" => (Command::Analyze, Format::Json),
+        "verify-install" => (Command::VerifyInstall, Format::Text),
+        "config" => (Command::Config, Format::Json),
+        "strip-test" => (Command::StripTest, Format::Text),
+        "init-pattern" => (Command::InitPattern, Format::Text),
+        _ => return usage_error(),
+    };
+    let format = match flag_value(args, "--format").map(Format::parse) {
+        Some(Some(f)) => f,
+        Some(None) => return usage_error(),
+        None => default_format,
+    };
+    if format == Format::Prometheus && !matches!(command, Command::Analyze) {
+        return usage_error();
+    }
+    let positional = args.get(1).filter(|a| !a.starts_with("--"));
+
+    let (target, result) = match command {
+        Command::Replay => {
+            let dir = match positional {
+                Some(d) => d.as_str(),
+                None => return usage_error(),
+            };
+            let by_day = match flag_value(args, "--group-by") {
+                Some("day") => true,
+                Some(_) => return usage_error(),
+                None => false,
+            };
+            let result = replay(Path::new(dir), flag_value(args, "--cwd"), by_day, &env_var)
+                .map(|report| (emit(&report, format, |style| report.render(style)), 0));
+            (dir.to_string(), result)
+        }
+        // CI gating: with `--exit-codes` (or `exit_codes` in the config, or
+        // `SESSION_REFLECT_EXIT_CODES=1`), a substantial session with no memory
+        // exits 2, so a pipeline step can fail on an uploaded transcript artifact:
+        //
+        //     session-reflect analyze session.jsonl --cwd . --exit-codes
+        //
+        // The counts are printed either way; the exit code is the verdict.
+        Command::Analyze => {
+            let path = match positional {
+                Some(p) => p.as_str(),
+                None if args.iter().any(|a| a == "--stdin") => "-",
+                None => return usage_error(),
+            };
+            let cwd = flag_value(args, "--cwd").unwrap_or("");
+            let mut config = Config::load(cwd, &env_var);
+            config.exit_codes |= args.iter().any(|a| a == "--exit-codes");
+            if let Some(since) = flag_value(args, "--since") {
+                config.since = match parse_rfc3339(since) {
+                    Some(at) => Some(Since {
+                        at,
+                        keep_untimestamped: !args.iter().any(|a| a == "--exclude-untimestamped"),
+                    }),
+                    None => return usage_error(),
+                };
+            }
+            // A pipe can't be seeked, so `max_transcript_bytes` doesn't apply to stdin
+            let analysis = if path == "-" {
+                Ok(analyze_transcript(io::stdin().lock(), &config))
+            } else {
+                open_transcript(Path::new(path), &config).map(|f| analyze_transcript(f, &config))
+            };
+            let result = analysis.map(|analysis| {
+                let output = match format {
+                    Format::Prometheus => metrics(&analysis, cwd, &config),
+                    _ => emit(&analysis, format, |_| key_values(&analysis)),
+                };
+                let blocks = stop_verdict(&analysis, cwd, &config).outcome() == Outcome::Uncaptured;
+                (output, if config.exit_codes && blocks { 2 } else { 0 })
+            });
+            (path.to_string(), result)
+        }
+        Command::VerifyInstall => {
+            let settings = match flag_value(args, "--settings")
+                .map(PathBuf::from)
+                .or_else(|| default_settings_path(&env_var))
+            {
+                Some(s) => s,
+                None => return usage_error(),
+            };
+            let result = verify_install(&settings, &env_var).map(|report| {
+                let status = u8::from(!report.ok());
+                (
+                    emit(&report, format, |style| render_install(&report, style)),
+                    status,
+                )
+            });
+            (settings.to_string_lossy().into_owned(), result)
+        }
+        Command::Config => {
+            let cwd = flag_value(args, "--cwd").unwrap_or("");
+            let (config, sources) = Config::load_with_sources(cwd, &env_var);
+            let dump = ConfigDump { config, sources };
+            let output = emit(&dump, format, |style| render_config(&dump, style));
+            (cwd.to_string(), Ok((output, 0)))
+        }
+        Command::StripTest => {
+            let dir = match positional {
+                Some(d) => d.as_str(),
+                None => return usage_error(),
+            };
+            let result = strip_test(Path::new(dir)).map(|report| {
+                let status = u8::from(!report.problems.is_empty());
+                (emit(&report, format, |style| report.render(style)), status)
+            });
+            (dir.to_string(), result)
+        }
+        Command::InitPattern => {
+            let cwd = Path::new(flag_value(args, "--cwd").unwrap_or("."));
+            let force = args.iter().any(|a| a == "--force");
+            let path = cwd.join(PATTERN_PATH);
+            let result = init_pattern(cwd, force).map(|path| {
+                let report = InitReport {
+                    path: path.to_string_lossy().into_owned(),
+                };
+                (
+                    emit(&report, format, |_| format!("wrote {}\n", report.path)),
+                    0,
+                )
+            });
+            (path.to_string_lossy().into_owned(), result)
+        }
+    };
+
+    match result {
+        Ok((output, status)) => {
+            print!("{}", output);
+            ExitCode::from(status)
+        }
+        Err(e) => {
+            warn(format_args!("{}: {}", target, e));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+enum Command {
+    Replay,
+    Analyze,
+    VerifyInstall,
+    Config,
+    StripTest,
+    InitPattern,
+}
+
+/// The effective config with the layer each key came from.
+#[derive(Serialize)]
+struct ConfigDump {
+    config: Config,
+    sources: BTreeMap<String, &'static str>,
+}
+
+/// `key = value` lines, with non-default sources marked.
+fn render_config(dump: &ConfigDump, style: &Style) -> String {
+    let values = match serde_json::to_value(&dump.config) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => return String::new(),
+    };
+    values
+        .iter()
+        .map(|(key, value)| match dump.sources.get(key) {
+            Some(&source) if source != "default" => {
+                format!(
+                    "{} = {}  {}\n",
+                    key,
+                    value,
+                    style.paint(DIM, &format!("({})", source))
+                )
+            }
+            _ => format!("{} = {}\n", key, value),
+        })
+        .collect()
+}
+
+/// One line per configured hook, then an overall verdict.
+fn render_install(report: &InstallReport, style: &Style) -> String {
+    let mut out = format!("running version: {}\n", report.expected);
+    for hook in &report.hooks {
+        let status = match &hook.version {
+            _ if hook.matches(&report.expected) => style.paint(GREEN, "ok"),
+            Some(v) => style.paint(RED, &format!("mismatch ({})", v)),
+            None if hook.resolved.is_none() => style.paint(RED, "binary not found"),
+            None => style.paint(RED, "no version reported"),
+        };
+        let target = hook.resolved.as_deref().unwrap_or(&hook.command);
+        out.push_str(&format!("{:<12} {} {}\n", hook.event, target, status));
+    }
+    if report.hooks.is_empty() {
+        out.push_str(&style.paint(RED, "no session-reflect hooks configured"));
+        out.push('\n');
+    }
+    out
+}
+
+/// Output format shared by all subcommands.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Format {
+    Text,
+    Json,
+    /// Prometheus text exposition, for a node_exporter textfile collector.
+    Prometheus,
+}
+
+impl Format {
+    fn parse(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "prometheus" => Some(Format::Prometheus),
+            _ => None,
+        }
+    }
+}
+
+/// Render `value` as one line of JSON, or as the subcommand's text form.
+fn emit<T: Serialize>(value: &T, format: Format, text: impl FnOnce(&Style) -> String) -> String {
+    match format {
+        Format::Json => serde_json::to_string(value).unwrap_or_default() + "\n",
+        Format::Text | Format::Prometheus => {
+            text(&Style::detect(&env_var, std::io::stdout().is_terminal()))
+        }
+    }
+}
+
+/// Gauges describing one transcript in Prometheus text exposition format.
+fn metrics(analysis: &Analysis, cwd: &str, config: &Config) -> String {
+    let blocked = stop_verdict(analysis, cwd, config).outcome() == Outcome::Uncaptured;
+    let gauges = [
+        (
+            "user_messages",
+            "User messages in the transcript.",
+            analysis.user_messages,
+        ),
+        (
+            "tool_turns",
+            "Effective tool-using turns.",
+            analysis.effective_tool_turns(config),
+        ),
+        (
+            "task_calls",
+            "Task (subagent) tool calls.",
+            analysis.task_calls,
+        ),
+        ("test_runs", "Test-runner invocations.", analysis.test_runs),
+        (
+            "tool_errors",
+            "Tool results flagged as errors.",
+            analysis.tool_errors,
+        ),
+        (
+            "memory_written",
+            "1 if memory capture is satisfied.",
+            analysis.captured(config) as usize,
+        ),
+        ("blocked", "1 if a Stop would block.", blocked as usize),
+    ];
+    gauges
+        .iter()
+        .map(|(name, help, value)| {
+            format!(
+                "# HELP session_reflect_{name} {help}\n# TYPE session_reflect_{name} gauge\nsession_reflect_{name} {value}\n",
+                name = name,
+                help = help,
+                value = value
+            )
+        })
+        .collect()
+}
+
+/// `key: value` lines from a struct's serde fields, for plain text output.
+fn key_values<T: Serialize>(value: &T) -> String {
+    let fields = match serde_json::to_value(value) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => return String::new(),
+    };
+    fields
+        .iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(s) => format!("{}: {}\n", key, s),
+            other => format!("{}: {}\n", key, other),
+        })
+        .collect()
+}
+
+fn usage_error() -> ExitCode {
+    if !quiet() {
+        eprintln!("{}", USAGE);
+    }
+    ExitCode::from(2)
+}
+
+/// Value following `--name` in the argument list.
+fn flag_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// Aggregate Stop outcomes over a directory of saved transcripts.
+#[derive(Default, Debug, Serialize)]
+pub struct ReplayReport {
+    pub total: usize,
+    pub would_block: usize,
+    pub allowed_with_memory: usize,
+    pub allowed_light: usize,
+    pub average_tool_turns: f64,
+    /// With `--group-by day`, one entry per UTC day, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<DayDigest>,
+}
+
+/// Substantial sessions on one day, and how many of them captured memory.
+#[derive(Default, Debug, Serialize, PartialEq)]
+pub struct DayDigest {
+    /// `YYYY-MM-DD`, from the first entry's `timestamp`, else the file's
+    /// modification time.
+    pub day: String,
+    pub substantial: usize,
+    pub captured: usize,
+}
+
+impl ReplayReport {
+    fn render(&self, style: &Style) -> String {
+        let counts = [
+            ("transcripts:", self.total.to_string(), BOLD),
+            ("would block:", self.would_block.to_string(), RED),
+            (
+                "allowed (memory):",
+                self.allowed_with_memory.to_string(),
+                GREEN,
+            ),
+            ("allowed (light):", self.allowed_light.to_string(), DIM),
+            (
+                "average tool-turns:",
+                format!("{:.1}", self.average_tool_turns),
+                BOLD,
+            ),
+        ];
+        let mut out: String = counts
+            .iter()
+            .map(|(label, value, code)| format!("{:<20} {}\n", label, style.paint(code, value)))
+            .collect();
+        for day in &self.days {
+            let rate = match day.substantial {
+                0 => "-".to_string(),
+                n => format!("{:.0}%", 100.0 * day.captured as f64 / n as f64),
+            };
+            out.push_str(&format!(
+                "{}  substantial {:>3}  captured {:>3}  {}\n",
+                day.day,
+                day.substantial,
+                day.captured,
+                style.paint(BOLD, &rate)
+            ));
+        }
+        out
+    }
+}
+
+/// Pattern files in a directory whose stripped body looks wrong.
+#[derive(Default, Debug, Serialize)]
+pub struct StripReport {
+    pub files: usize,
+    pub problems: Vec<StripProblem>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct StripProblem {
+    pub path: String,
+    /// `empty body`, `leading ---` or `leading # `.
+    pub issue: &'static str,
+}
+
+impl StripReport {
+    fn render(&self, style: &Style) -> String {
+        let mut out: String = self
+            .problems
+            .iter()
+            .map(|p| format!("{}: {}\n", p.path, style.paint(RED, p.issue)))
+            .collect();
+        let summary = format!("{} files, {} problems", self.files, self.problems.len());
+        let code = if self.problems.is_empty() { GREEN } else { RED };
+        out.push_str(&style.paint(code, &summary));
+        out.push('\n');
+        out
+    }
+}
+
+/// Run `strip_frontmatter_and_h1` over every `.md` in `dir`, flagging an
+/// empty result or one that still opens with a delimiter or an H1.
+pub fn strip_test(dir: &Path) -> std::io::Result<StripReport> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "md"))
+        .collect();
+    paths.sort();
+
+    let mut report = StripReport::default();
+    for path in paths {
+        let content = match read_text(&path) {
+            Some(c) => c,
+            None => continue,
+        };
+        report.files += 1;
+        let body = strip_frontmatter_and_h1(&content);
+        let body = body.trim_start();
+        let issue = if body.trim().is_empty() {
+            "empty body"
+        } else if body.starts_with("---") {
+            "leading ---"
+        } else if body.starts_with("# ") {
+            "leading # "
+        } else {
+            continue;
+        };
+        report.problems.push(StripProblem {
+            path: path.to_string_lossy().into_owned(),
+            issue,
+        });
+    }
+    Ok(report)
+}
+
+/// What `init-pattern` writes: a pattern whose stripped body uses the
+/// template placeholders, as a starting point to edit.
+pub const STARTER_PATTERN: &str = "---
+type: pattern
+---
+# Session Reflect
+
+This session ran {{tool_turns}} tool turns over {{user_messages}} messages. Before ending it,
+look back over what happened and capture what's worth keeping:
+
+- **Learnings** in `Memory/Learnings/`: anything surprising, a pitfall you hit, or a technique
+  that worked and will apply again.
+- **Decisions** in `Memory/Decisions/`: choices made and why, including alternatives ruled out.
+
+Skip anything already recorded, and keep each note short enough to scan.
+";
+
+#[derive(Serialize)]
+struct InitReport {
+    path: String,
+}
+
+/// Write `STARTER_PATTERN` to `PATTERN_PATH` under `cwd`, creating its
+/// directories. An existing file is kept unless `force` is set.
+pub fn init_pattern(cwd: &Path, force: bool) -> io::Result<PathBuf> {
+    let path = cwd.join(PATTERN_PATH);
+    if !force && path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "already exists (--force to overwrite)",
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, STARTER_PATTERN)?;
+    Ok(path)
+}
+
+const RED: &str = "31";
+const GREEN: &str = "32";
+const BOLD: &str = "1";
+const DIM: &str = "2";
+
+/// ANSI styling for human-readable subcommand output. Machine-readable
+/// output (hook JSON) never goes through here.
+struct Style {
+    enabled: bool,
+}
+
+impl Style {
+    /// Color only when stdout is a terminal and `NO_COLOR` is unset or empty.
+    fn detect(env: &dyn Fn(&str) -> Option<String>, is_tty: bool) -> Style {
+        let no_color = env("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Style {
+            enabled: is_tty && !no_color,
+        }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Run the Stop evaluation over every `*.jsonl` in `dir`. Config is loaded
+/// per transcript against `cwd`, or the cwd recorded in the transcript.
+/// `by_day` also tallies each day's substantial sessions.
+pub fn replay(
+    dir: &Path,
+    cwd: Option<&str>,
+    by_day: bool,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> std::io::Result<ReplayReport> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    paths.sort();
+
+    // Parsing limits come from the base config; thresholds may be per-project.
+    let base = Config::load(cwd.unwrap_or(""), env);
+    let mut report = ReplayReport::default();
+    let mut tool_turns = 0;
+    let mut days: BTreeMap<String, DayDigest> = BTreeMap::new();
+
+    for path in paths {
+        let file = match open_transcript(&path, &base) {
+            Ok(f) => f,
+            Err(_) => continue,
+        };
+        let analysis = analyze_transcript(file, &base);
+        let session_cwd = cwd.or(analysis.cwd.as_deref()).unwrap_or("");
+        let config = Config::load(session_cwd, env);
+
+        report.total += 1;
+        tool_turns += analysis.effective_tool_turns(&config);
+        let outcome = stop_verdict(&analysis, session_cwd, &config).outcome();
+        match outcome {
+            Outcome::Light => report.allowed_light += 1,
+            Outcome::Captured => report.allowed_with_memory += 1,
+            Outcome::Uncaptured => report.would_block += 1,
+        }
+
+        if by_day {
+            let at = analysis
+                .started_at
+                .or_else(|| modified_secs(&path))
+                .unwrap_or(0);
+            let day = utc_date(at);
+            let digest = days.entry(day.clone()).or_insert_with(|| DayDigest {
+                day,
+                ..DayDigest::default()
+            });
+            if outcome != Outcome::Light {
+                digest.substantial += 1;
+            }
+            if outcome == Outcome::Captured {
+                digest.captured += 1;
+            }
+        }
+    }
+    report.days = days.into_values().collect();
+
+    if report.total > 0 {
+        report.average_tool_turns = tool_turns as f64 / report.total as f64;
+    }
+    Ok(report)
+}
+
+/// `path`'s modification time in seconds since the epoch.
+fn modified_secs(path: &Path) -> Option<i64> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TOOL_TURN_THRESHOLD;
+    use crate::test_support::*;
+
+    #[test]
+    fn test_replay_reports_mixed_sessions() {
+        let dir = TempDir::new("replay");
+        let blocked = substantial_transcript(&[]);
+        let captured =
+            substantial_transcript(&[memory_write_turn("Write", "Memory/Learnings/x.md")]);
+        let light = tool_turn("Bash");
+        let excused = substantial_transcript(&[user_message("[[session-reflect:allow]]")]);
+        fs::write(dir.path().join("a.jsonl"), &blocked).unwrap();
+        fs::write(dir.path().join("b.jsonl"), &captured).unwrap();
+        fs::write(dir.path().join("c.jsonl"), &light).unwrap();
+        fs::write(dir.path().join("d.jsonl"), &excused).unwrap();
+        fs::write(dir.path().join("notes.txt"), &blocked).unwrap();
+
+        let report = replay(dir.path(), Some(""), false, &|_| None).unwrap();
+        assert_eq!(report.total, 4);
+        assert_eq!(report.would_block, 1);
+        assert_eq!(report.allowed_with_memory, 1);
+        assert_eq!(report.allowed_light, 2);
+        let expected = (3 * TOOL_TURN_THRESHOLD + 2) as f64 / 4.0;
+        assert!((report.average_tool_turns - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_replay_groups_by_day() {
+        let dir = TempDir::new("replay-by-day");
+        let on = |day: &str, transcript: &str| {
+            let stamp = format!(r#"{{"type":"human","timestamp":"{}T10:00:00Z"}}"#, day);
+            format!("{}\n{}", stamp, transcript)
+        };
+        let blocked = substantial_transcript(&[]);
+        let captured =
+            substantial_transcript(&[memory_write_turn("Write", "Memory/Learnings/x.md")]);
+        fs::write(dir.path().join("a.jsonl"), on("2026-10-13", &blocked)).unwrap();
+        fs::write(dir.path().join("b.jsonl"), on("2026-10-13", &captured)).unwrap();
+        fs::write(dir.path().join("c.jsonl"), on("2026-10-14", &captured)).unwrap();
+        fs::write(
+            dir.path().join("d.jsonl"),
+            on("2026-10-14", &tool_turn("Bash")),
+        )
+        .unwrap();
+
+        let digest = |day: &str, substantial, captured| DayDigest {
+            day: day.to_string(),
+            substantial,
+            captured,
+        };
+        let report = replay(dir.path(), Some(""), true, &|_| None).unwrap();
+        assert_eq!(
+            report.days,
+            [digest("2026-10-13", 2, 1), digest("2026-10-14", 1, 1)]
+        );
+        let text = report.render(&Style::detect(&|_| None, false));
+        assert!(
+            text.contains("2026-10-13  substantial   2  captured   1  50%\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("2026-10-14  substantial   1  captured   1  100%\n"),
+            "{}",
+            text
+        );
+
+        assert!(replay(dir.path(), Some(""), false, &|_| None)
+            .unwrap()
+            .days
+            .is_empty());
+    }
+
+    #[test]
+    fn test_strip_test_flags_pathological_patterns() {
+        let dir = TempDir::new("strip-test");
+        let write = |name: &str, text: &str| fs::write(dir.path().join(name), text).unwrap();
+        write(
+            "normal.md",
+            "---\ntype: pattern\n---\n# Session Reflect\n\nCapture learnings.\n",
+        );
+        write("double.md", "---\na: 1\n---\n---\nb: 2\n---\nBody\n");
+        write("two-h1.md", "# One\n# Two\nBody\n");
+        write("bare.md", "---\ntype: pattern\n---\n# Only a title\n");
+        write("notes.txt", "");
+
+        let report = strip_test(dir.path()).unwrap();
+        assert_eq!(report.files, 4);
+        let issues: Vec<(&str, &str)> = report
+            .problems
+            .iter()
+            .map(|p| {
+                (
+                    Path::new(&p.path).file_name().unwrap().to_str().unwrap(),
+                    p.issue,
+                )
+            })
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                ("bare.md", "empty body"),
+                ("double.md", "leading ---"),
+                ("two-h1.md", "leading # ")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_init_pattern_writes_starter_once() {
+        let dir = TempDir::new("init-pattern");
+        let path = init_pattern(dir.path(), false).unwrap();
+        assert_eq!(path, dir.path().join(PATTERN_PATH));
+
+        let body = strip_frontmatter_and_h1(&fs::read_to_string(&path).unwrap());
+        assert!(!body.trim().is_empty());
+        assert!(body.contains("{{tool_turns}}") && body.contains("{{user_messages}}"));
+        assert_eq!(strip_test(path.parent().unwrap()).unwrap().problems, []);
+
+        fs::write(&path, "# Mine\n\nKeep this.\n").unwrap();
+        let err = init_pattern(dir.path(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Mine\n\nKeep this.\n");
+        init_pattern(dir.path(), true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), STARTER_PATTERN);
+    }
+
+    #[test]
+    fn test_no_color_disables_ansi() {
+        let report = ReplayReport {
+            total: 2,
+            would_block: 1,
+            ..ReplayReport::default()
+        };
+
+        let colored = Style::detect(&|_| None, true);
+        assert!(report.render(&colored).contains('\x1b'));
+
+        let env = |key: &str| (key == "NO_COLOR").then(|| "1".to_string());
+        let plain = report.render(&Style::detect(&env, true));
+        assert!(!plain.contains('\x1b'));
+        assert!(plain.contains("would block:"));
+
+        assert!(!report
+            .render(&Style::detect(&|_| None, false))
+            .contains('\x1b'));
+    }
+}
@@ -0,0 +1,191 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of wall-clock time, injectable so time-based gates can be tested.
+pub trait Clock {
+    /// Seconds since the Unix epoch.
+    fn now(&self) -> u64;
+
+    /// Local time's offset from UTC, in seconds east.
+    fn utc_offset(&self) -> i64 {
+        0
+    }
+
+    /// Seconds since local midnight.
+    fn local_time_of_day(&self) -> u64 {
+        (self.now() as i64 + self.utc_offset()).rem_euclid(86_400) as u64
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    /// Asks `date`, which knows the zone database and honors `TZ`; std has
+    /// no notion of local time. UTC if that fails.
+    fn utc_offset(&self) -> i64 {
+        Command::new("date")
+            .arg("+%z")
+            .output()
+            .ok()
+            .and_then(|out| parse_utc_offset(String::from_utf8_lossy(&out.stdout).trim()))
+            .unwrap_or(0)
+    }
+}
+
+/// A clock stuck at a given instant, in UTC.
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
+
+/// `+0200` / `-0530` as seconds east of UTC.
+fn parse_utc_offset(text: &str) -> Option<i64> {
+    let (sign, digits) = match text.split_at_checked(1)? {
+        ("+", d) => (1, d),
+        ("-", d) => (-1, d),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// An RFC 3339 timestamp (`2026-10-14T13:05:00Z`, `2026-10-14T15:05:00.250+02:00`)
+/// as whole seconds since the Unix epoch; fractional seconds are dropped.
+pub fn parse_rfc3339(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let digits = |range: std::ops::Range<usize>| -> Option<i64> {
+        let part = text.get(range)?;
+        part.bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| part.parse().ok())?
+    };
+    let (year, month, day) = (digits(0..4)?, digits(5..7)?, digits(8..10)?);
+    let (hour, minute, second) = (digits(11..13)?, digits(14..16)?, digits(17..19)?);
+    let separators = [(4, b'-'), (7, b'-'), (13, b':'), (16, b':')];
+    if separators.iter().any(|&(i, c)| text.as_bytes()[i] != c)
+        || !matches!(text.as_bytes()[10], b'T' | b't' | b' ')
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let mut rest = &text[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let end = fraction
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(fraction.len());
+        if end == 0 {
+            return None;
+        }
+        rest = &fraction[end..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let (sign, hhmm) = match rest.split_at_checked(1)? {
+                ("+", d) => ("+", d),
+                ("-", d) => ("-", d),
+                _ => return None,
+            };
+            match hhmm.split_once(':') {
+                Some((h, m)) if h.len() == 2 && m.len() == 2 => {
+                    parse_utc_offset(&format!("{}{}{}", sign, h, m))?
+                }
+                _ => return None,
+            }
+        }
+    };
+
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// The UTC calendar date (`2026-10-14`) of `secs` since the Unix epoch.
+pub fn utc_date(secs: i64) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Days from 1970-01-01 to the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_utc_offset() {
+        assert_eq!(parse_utc_offset("+0200"), Some(7200));
+        assert_eq!(parse_utc_offset("-0530"), Some(-19_800));
+        assert_eq!(parse_utc_offset("0200"), None);
+        assert_eq!(parse_utc_offset(""), None);
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_rfc3339("2026-10-14T13:05:00Z"), Some(1_791_983_100));
+        assert_eq!(
+            parse_rfc3339("2026-10-14T15:05:00.250+02:00"),
+            Some(1_791_983_100)
+        );
+        assert_eq!(
+            parse_rfc3339("2024-02-29 23:59:59-00:30"),
+            Some(1_709_251_199 + 1800)
+        );
+        assert_eq!(parse_rfc3339("2026-10-14"), None);
+        assert_eq!(parse_rfc3339("2026-13-14T13:05:00Z"), None);
+        assert_eq!(parse_rfc3339("2026-10-14T13:05:00"), None);
+    }
+
+    #[test]
+    fn test_utc_date() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(1_791_983_100), "2026-10-14");
+        assert_eq!(utc_date(1_709_251_199), "2024-02-29");
+        assert_eq!(utc_date(-1), "1969-12-31");
+    }
+}
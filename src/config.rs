@@ -0,0 +1,981 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::decision::{HookEvent, SESSION_START_RESUME};
+use crate::output::OutputSchema;
+use crate::pattern::{read_text, strip_frontmatter_and_h1, PATTERN_ENV, PATTERN_PATH};
+use crate::warn;
+
+pub const TOOL_TURN_THRESHOLD: usize = 10;
+pub const USER_MSG_THRESHOLD: usize = 4;
+
+const ENV_PREFIX: &str = "SESSION_REFLECT_";
+
+/// Thresholds above this are almost certainly typos.
+const THRESHOLD_CEILING: usize = 10_000;
+
+const DEFAULT_TEST_RUNNERS: &[&str] = &[
+    "cargo test",
+    "cargo nextest",
+    "pytest",
+    "npm test",
+    "go test",
+    "jest",
+    "vitest",
+];
+
+const DEFAULT_DECLINE_PHRASES: &[&str] = &[
+    "no need to capture",
+    "nothing to capture",
+    "don't capture",
+    "skip reflection",
+    "skip the reflection",
+];
+
+const DEFAULT_RESEARCH_TOOLS: &[&str] = &["WebFetch", "WebSearch"];
+
+const DEFAULT_CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "jsx", "ts", "tsx", "go", "java", "kt", "c", "h", "cc", "cpp", "hpp", "cs",
+    "rb", "swift", "php", "scala", "sh",
+];
+
+const DEFAULT_READ_ONLY_TOOLS: &[&str] = &[
+    "Read",
+    "Grep",
+    "Glob",
+    "LS",
+    "WebFetch",
+    "WebSearch",
+    "NotebookRead",
+    "TodoRead",
+];
+
+/// Entry `type` values that occur in transcripts; anything else in
+/// `turn_entry_types` is a typo.
+pub const KNOWN_ENTRY_TYPES: &[&str] = &["assistant", "user", "human", "system", "summary"];
+
+/// Tunables layered from the plugin's `config.json` and `SESSION_REFLECT_*`
+/// environment variables (env wins). Keys the hook doesn't know about — such
+/// as the paths used by `/reflect` — are ignored.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Tool-turns credited per `Task` call, since delegated subagent work
+    /// shows up as a single tool_use in the main transcript. 0 leaves
+    /// `Task` calls out of the count entirely.
+    pub task_weight: usize,
+    /// Count every tool call toward the tool-turn threshold instead of each
+    /// tool-using turn, so one message with several interleaved calls
+    /// weighs as much as the same calls spread over separate turns.
+    pub count_tool_calls_not_turns: bool,
+    /// Map decisions to exit codes (see `Decision::exit_code`).
+    pub exit_codes: bool,
+    /// Only `Write` to a memory path satisfies capture; `Edit` doesn't.
+    pub require_write_not_edit: bool,
+    /// Transcript lines longer than this are skipped unparsed.
+    pub max_line_bytes: usize,
+    /// Print analysis diagnostics to stderr.
+    pub debug: bool,
+    /// Minimum user messages for a substantial session.
+    pub user_msg_threshold: usize,
+    /// User messages with fewer characters than this (trimmed) don't count
+    /// toward `user_msg_threshold`.
+    pub min_user_message_chars: usize,
+    /// Minimum (effective) tool-using turns for a substantial session.
+    pub tool_turn_threshold: usize,
+    /// Score mode: a session is substantial once `user_weight * user_messages
+    /// + tool_weight * tool_turns` reaches this, in place of requiring both
+    /// `user_msg_threshold` and `tool_turn_threshold`.
+    pub score_threshold: Option<f64>,
+    /// Score per user message in score mode.
+    pub user_weight: f64,
+    /// Score per (effective) tool-using turn in score mode.
+    pub tool_weight: f64,
+    /// Only analyze the last N parseable entries, so stale early activity
+    /// in a long session doesn't count.
+    pub tail_turns: Option<usize>,
+    /// A memory write stops counting once more than this many user messages
+    /// have followed it.
+    pub memory_staleness: Option<usize>,
+    /// Only a memory write in the session's last N assistant turns counts.
+    pub memory_recency_turns: Option<usize>,
+    /// Shape of the JSON printed on stdout.
+    pub output_schema: OutputSchema,
+    /// Pretty-print the stdout JSON.
+    pub pretty: bool,
+    /// `output_schema`/`pretty` for Stop output only.
+    pub stop: EventOutput,
+    /// `output_schema`/`pretty` for PreCompact output only.
+    pub precompact: EventOutput,
+    /// A repeat block on an identical transcript within this many seconds
+    /// is downgraded to allow (0 disables).
+    pub dedupe_window_secs: u64,
+    /// Which entry types count as tool-using turns and as user messages.
+    pub turn_entry_types: TurnEntryTypes,
+    /// Substrings of a `Bash` command that mark it as a test run.
+    pub test_runner_patterns: Vec<String>,
+    /// Phrases (case-insensitive) in the latest user message that decline
+    /// reflection for the session. Empty turns the check off.
+    pub decline_phrases: Vec<String>,
+    /// This many test runs make a session substantial regardless of its
+    /// tool-turn count.
+    pub test_run_threshold: Option<usize>,
+    /// This many failed tool calls make a session substantial regardless
+    /// of its tool-turn count.
+    pub error_threshold: Option<usize>,
+    /// This many characters of `Bash` commands in total make a session
+    /// substantial regardless of its tool-turn count.
+    pub bash_chars_threshold: Option<usize>,
+    /// This many assistant turns, with or without tool use, make a session
+    /// substantial regardless of its tool-turn count.
+    pub assistant_turn_threshold: Option<usize>,
+    /// `cwd` globs (`*` within a path segment, `**` across segments, `?` one
+    /// character, leading `~/` for `HOME`) where any session with a mutating
+    /// call and a user message counts as substantial.
+    pub always_substantial_globs: Vec<String>,
+    /// Tools whose calls count as research.
+    pub research_tools: Vec<String>,
+    /// This many research calls make a session substantial, even one that
+    /// is otherwise read-only.
+    pub research_threshold: Option<usize>,
+    /// Append one line per tripped gate to the block reason.
+    pub verbose_reasons: bool,
+    /// End the block reason with the absolute path of the pattern file it
+    /// came from; nothing is added for the built-in fallback.
+    pub include_pattern_path: bool,
+    /// End the prompt with `git log --oneline` for `cwd`, when it's a repo.
+    pub include_git_log: bool,
+    /// Commits listed by `include_git_log`.
+    pub git_log_lines: usize,
+    /// Cut the block reason to this many characters, ending in `…`.
+    pub max_reason_chars: Option<usize>,
+    /// Never block a session whose only file edits were to source code.
+    pub skip_if_code_only: bool,
+    /// Mutating calls per read call a session needs before `patterns` and
+    /// `skip_if_code_only` treat it by what it edited.
+    pub min_edit_intensity: Option<f64>,
+    /// File extensions (without the dot) that count as source code.
+    pub code_extensions: Vec<String>,
+    /// Tools that only look around; every other tool counts as mutating.
+    pub read_only_tools: Vec<String>,
+    /// Never block a session that made no mutating tool calls, however
+    /// much it read.
+    pub allow_read_only_sessions: bool,
+    /// Allow a session once more than this fraction of its tool calls were
+    /// `Grep`/`Glob` searches, treating it as exploration.
+    pub max_search_ratio: Option<f64>,
+    /// On PreCompact, skip the prompt when the transcript shows memory was
+    /// already captured.
+    pub precompact_skip_if_recent_memory: bool,
+    /// After a full PreCompact prompt, later automatic compactions within
+    /// this many seconds get a one-line reminder instead (0 disables).
+    pub precompact_cooldown_secs: u64,
+    /// Render `[[Target]]` / `[[Target|Alias]]` in the prompt as plain text.
+    pub resolve_wikilinks: bool,
+    /// `${VAR}` in the prompt from the environment, outside code fences.
+    pub interpolate_env: InterpolateEnv,
+    /// Sessions using fewer distinct tools than this are never substantial.
+    pub distinct_tools_threshold: Option<usize>,
+    /// Tools whose mere invocation counts as a memory write.
+    pub capture_tools: Vec<String>,
+    /// Frontmatter tags (`type:`/`tags:`) that make a write to any `.md`
+    /// file count as a memory write. Empty keeps capture directory-based.
+    pub capture_by_tag: Vec<String>,
+    /// Single files (relative to the vault root, else the transcript's
+    /// `cwd`) any write to which counts as capture, e.g. a `DECISIONS.md` log.
+    pub capture_files: Vec<String>,
+    /// Further places a write counts as capture, alongside `MEMORY_PATHS`.
+    pub capture_rules: Vec<CaptureRule>,
+    /// A memory write only counts when the text it leaves, trimmed, has at
+    /// least this many characters.
+    pub min_memory_chars: Option<usize>,
+    /// Don't credit a memory write whose file the session later deleted,
+    /// nor a session that deleted more memory files than it wrote; the
+    /// block reason lists the deleted files.
+    pub penalize_memory_deletion: bool,
+    /// Candidate pattern files (relative to `cwd`), tried in order; the
+    /// first with a non-empty body is the main prompt.
+    pub pattern_paths: Vec<String>,
+    /// More pattern files (relative to `cwd`) whose bodies follow the main
+    /// prompt.
+    pub extra_patterns: Vec<String>,
+    /// Joins the main prompt and each `extra_patterns` body; `\n` in the
+    /// value is a newline.
+    pub pattern_separator: String,
+    /// Pattern file (relative to `cwd`) per work-type label (`code`,
+    /// `docs`, `shell`), used instead of the default pattern when the
+    /// session's dominant activity matches.
+    pub patterns: BTreeMap<String, String>,
+    /// Fall back to the newest transcript in Claude's project directory when
+    /// the payload's `transcript_path` is missing or unreadable.
+    pub discover_transcript: bool,
+    /// Allow an uncaptured Stop when any of this many of the project's most
+    /// recent other transcripts (found as for `discover_transcript`)
+    /// captured memory.
+    pub lookback_sessions: usize,
+    /// Allow uncaptured substantial Stops while the project's last memory
+    /// write (in any session) is at most this many hours old.
+    pub max_capture_gap_hours: Option<u64>,
+    /// Count only what follows the transcript's last compaction marker.
+    pub reset_on_compaction: bool,
+    /// Only the last this-many bytes of a larger transcript are analyzed.
+    pub max_transcript_bytes: Option<u64>,
+    /// Block only once this many uncaptured substantial sessions have
+    /// happened in a row in a project.
+    pub block_after_streak: usize,
+    /// Don't enforce the first N substantial sessions in a new project.
+    pub warmup_sessions: usize,
+    /// On every Stop that isn't blocked, still surface the prompt as
+    /// `additionalContext`.
+    pub always_inject_on_stop: bool,
+    /// `additionalContext` for SessionStart, per `source` (`startup`,
+    /// `resume`, `clear`, `compact`). Sources without a message are silent.
+    pub session_start_messages: BTreeMap<String, String>,
+    /// Shell command run (best-effort, with a short timeout) whenever a
+    /// Stop blocks.
+    pub notify_command: Option<String>,
+    /// Unix socket the hook output is also written to (best-effort), for a
+    /// long-lived supervisor.
+    pub forward_socket: Option<PathBuf>,
+    /// Send a line per decision, with its counts, to syslog (Unix only,
+    /// best-effort).
+    pub log_syslog: bool,
+    /// Local `HH:MM-HH:MM` window in which blocks are enforced; it may wrap
+    /// past midnight. Unset enforces around the clock.
+    pub active_hours: Option<String>,
+    /// What a block becomes outside `active_hours`.
+    pub outside_hours: OutsideHours,
+    /// Credit only writes under the `Memory/` of the nearest enclosing vault.
+    pub discover_memory_root: bool,
+    /// The vault found by `discover_memory_root`: the nearest ancestor of
+    /// `cwd`, up to the data root, that contains `Memory/`.
+    #[serde(skip)]
+    pub memory_root: Option<PathBuf>,
+    /// How paths under `HOME` appear in debug output.
+    pub redact_paths: RedactPaths,
+    /// `HOME` as of loading, for `redact_paths`.
+    #[serde(skip)]
+    pub home: Option<String>,
+    /// Only count transcript entries from this point on (`analyze --since`).
+    #[serde(skip)]
+    pub since: Option<Since>,
+}
+
+/// A cut-off for transcript entries by their `timestamp`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Since {
+    /// Seconds since the Unix epoch.
+    pub at: i64,
+    /// Whether entries without a parseable `timestamp` still count.
+    pub keep_untimestamped: bool,
+}
+
+/// A path prefix under which a write counts as capture, optionally only
+/// when the written text or the file on disk contains `contains`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct CaptureRule {
+    pub path: String,
+    #[serde(default)]
+    pub contains: Option<String>,
+}
+
+/// Output settings for one hook event; unset ones fall back to the
+/// top-level `output_schema` and `pretty`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Default)]
+#[serde(default)]
+pub struct EventOutput {
+    pub output_schema: Option<OutputSchema>,
+    pub pretty: Option<bool>,
+}
+
+/// Treatment of a Stop block outside `active_hours`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutsideHours {
+    /// Block as usual.
+    #[default]
+    Block,
+    /// Surface the reason as `additionalContext` without blocking.
+    Inject,
+    /// Let the session end silently.
+    Allow,
+}
+
+/// What `${VAR}` in the prompt becomes.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InterpolateEnv {
+    /// Left as written.
+    #[default]
+    Off,
+    /// The variable's value; unset ones are left as written.
+    Keep,
+    /// The variable's value; unset ones become empty.
+    Blank,
+}
+
+/// Path redaction in debug output; decisions always see real paths.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactPaths {
+    #[default]
+    Off,
+    /// `~` in place of the home directory.
+    Home,
+    /// `~/` followed by a hash of the rest of the path.
+    Hash,
+}
+
+/// Transcript `type` values mapped to what they count as.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TurnEntryTypes {
+    /// Entries whose `tool_use` blocks count toward `tool_using_turns`.
+    pub turn: Vec<String>,
+    /// Entries that count as user messages.
+    pub user: Vec<String>,
+}
+
+impl Default for TurnEntryTypes {
+    fn default() -> Self {
+        TurnEntryTypes {
+            turn: vec!["assistant".to_string()],
+            user: vec!["user".to_string(), "human".to_string()],
+        }
+    }
+}
+
+impl TurnEntryTypes {
+    /// Drop types no transcript produces, warning about each.
+    fn retain_known(&mut self) {
+        for (role, types) in [("turn", &mut self.turn), ("user", &mut self.user)] {
+            types.retain(|t| {
+                let known = KNOWN_ENTRY_TYPES.contains(&t.as_str());
+                if !known {
+                    warn(format_args!(
+                        "ignoring unknown {} entry type {:?} in turn_entry_types",
+                        role, t
+                    ));
+                }
+                known
+            });
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            task_weight: 1,
+            count_tool_calls_not_turns: false,
+            exit_codes: false,
+            require_write_not_edit: false,
+            max_line_bytes: 4 * 1024 * 1024,
+            debug: false,
+            user_msg_threshold: USER_MSG_THRESHOLD,
+            min_user_message_chars: 0,
+            tool_turn_threshold: TOOL_TURN_THRESHOLD,
+            score_threshold: None,
+            user_weight: 1.0,
+            tool_weight: 1.0,
+            tail_turns: None,
+            memory_staleness: None,
+            memory_recency_turns: None,
+            output_schema: OutputSchema::Legacy,
+            pretty: false,
+            stop: EventOutput::default(),
+            precompact: EventOutput::default(),
+            dedupe_window_secs: 10,
+            turn_entry_types: TurnEntryTypes::default(),
+            test_runner_patterns: DEFAULT_TEST_RUNNERS.iter().map(|p| p.to_string()).collect(),
+            decline_phrases: DEFAULT_DECLINE_PHRASES
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            test_run_threshold: None,
+            error_threshold: None,
+            bash_chars_threshold: None,
+            assistant_turn_threshold: None,
+            always_substantial_globs: Vec::new(),
+            research_tools: DEFAULT_RESEARCH_TOOLS
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            research_threshold: None,
+            verbose_reasons: false,
+            include_pattern_path: false,
+            include_git_log: false,
+            git_log_lines: 5,
+            max_reason_chars: None,
+            skip_if_code_only: false,
+            min_edit_intensity: None,
+            code_extensions: DEFAULT_CODE_EXTENSIONS
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+            read_only_tools: DEFAULT_READ_ONLY_TOOLS
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            allow_read_only_sessions: true,
+            max_search_ratio: None,
+            precompact_skip_if_recent_memory: false,
+            precompact_cooldown_secs: 0,
+            resolve_wikilinks: false,
+            interpolate_env: InterpolateEnv::Off,
+            distinct_tools_threshold: None,
+            capture_tools: Vec::new(),
+            capture_by_tag: Vec::new(),
+            capture_files: Vec::new(),
+            capture_rules: Vec::new(),
+            min_memory_chars: None,
+            penalize_memory_deletion: false,
+            pattern_paths: vec![PATTERN_PATH.to_string()],
+            extra_patterns: Vec::new(),
+            pattern_separator: "\n\n".to_string(),
+            patterns: BTreeMap::new(),
+            discover_transcript: false,
+            lookback_sessions: 0,
+            max_capture_gap_hours: None,
+            reset_on_compaction: false,
+            max_transcript_bytes: None,
+            block_after_streak: 1,
+            warmup_sessions: 0,
+            always_inject_on_stop: false,
+            session_start_messages: [("resume".to_string(), SESSION_START_RESUME.to_string())]
+                .into(),
+            notify_command: None,
+            forward_socket: None,
+            log_syslog: false,
+            active_hours: None,
+            outside_hours: OutsideHours::Block,
+            discover_memory_root: false,
+            memory_root: None,
+            redact_paths: RedactPaths::Off,
+            home: None,
+            since: None,
+        }
+    }
+}
+
+impl Config {
+    /// Merge the layers, lowest precedence first: pattern frontmatter, the
+    /// config file, then env overrides. Env and frontmatter values are
+    /// coerced to the type of the corresponding default, so
+    /// `SESSION_REFLECT_TASK_WEIGHT=3` works without JSON quoting. A value
+    /// that doesn't fit its key is dropped with a warning, leaving the layer
+    /// beneath in place.
+    pub fn load(cwd: &str, env: &dyn Fn(&str) -> Option<String>) -> Config {
+        Config::load_with_sources(cwd, env).0
+    }
+
+    /// [`Config::load`], also reporting which layer supplied each key:
+    /// `default`, `frontmatter`, `file` or `env`.
+    pub fn load_with_sources(
+        cwd: &str,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> (Config, BTreeMap<String, &'static str>) {
+        let defaults = match serde_json::to_value(Config::default()) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+        let mut merged = Map::new();
+        let mut sources: BTreeMap<String, &'static str> =
+            defaults.keys().map(|k| (k.clone(), "default")).collect();
+        // Each known key is checked on its own against the defaults, so one
+        // bad value costs only that key
+        let mut layer = |merged: &mut Map<String, Value>, values: Map<String, Value>, name| {
+            for (key, value) in values {
+                if sources.contains_key(&key) {
+                    let mut probe = defaults.clone();
+                    probe.insert(key.clone(), value.clone());
+                    if let Err(e) = serde_json::from_value::<Config>(Value::Object(probe)) {
+                        warn(format_args!(
+                            "invalid {} from {} ({}), ignoring",
+                            key, name, e
+                        ));
+                        continue;
+                    }
+                    sources.insert(key.clone(), name);
+                }
+                merged.insert(key, value);
+            }
+        };
+
+        let file = match config_file_path(env)
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|t| serde_json::from_str(&t).ok())
+        {
+            Some(Value::Object(file)) => file,
+            _ => Map::new(),
+        };
+
+        let mut overrides: Map<String, Value> = defaults
+            .iter()
+            .filter_map(|(key, default)| {
+                let var = format!("{}{}", ENV_PREFIX, key.to_uppercase());
+                let raw = env(&var)?;
+                let value = coerce_env(&raw, default);
+                if value.is_none() {
+                    warn(format_args!(
+                        "invalid {} from env ({}={:?}), ignoring",
+                        key, var, raw
+                    ));
+                }
+                value.map(|value| (key.clone(), value))
+            })
+            .collect();
+        if let Some(paths) = env(PATTERN_ENV).filter(|p| !p.is_empty()) {
+            let paths = paths
+                .split(':')
+                .filter(|p| !p.is_empty())
+                .map(Value::from)
+                .collect();
+            overrides.insert("pattern_paths".to_string(), Value::Array(paths));
+        }
+
+        // Frontmatter sits beneath the other layers but comes from whichever
+        // pattern file they point at
+        let pattern_paths = [&overrides, &file, &defaults]
+            .into_iter()
+            .find_map(|layer| layer.get("pattern_paths"))
+            .and_then(Value::as_array)
+            .map(|paths| paths.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if let Some(content) = pattern_paths
+            .iter()
+            .filter_map(|path| read_text(&Path::new(cwd).join(path)))
+            .find(|content| !strip_frontmatter_and_h1(content).trim().is_empty())
+        {
+            layer(
+                &mut merged,
+                frontmatter_overrides(&content, &defaults),
+                "frontmatter",
+            );
+        }
+        layer(&mut merged, file, "file");
+        layer(&mut merged, overrides, "env");
+
+        let mut config: Config =
+            serde_json::from_value(Value::Object(merged)).unwrap_or_else(|e| {
+                warn(format_args!("invalid config ({}), using defaults", e));
+                sources.values_mut().for_each(|s| *s = "default");
+                Config::default()
+            });
+        config.turn_entry_types.retain_known();
+        if config.discover_memory_root {
+            config.memory_root = data_root(env).and_then(|root| discover_memory_root(cwd, &root));
+        }
+        config.home = env("HOME").filter(|h| !h.is_empty());
+        if let Some(hours) = config.active_hours.as_deref() {
+            if parse_active_hours(hours).is_none() {
+                warn(format_args!(
+                    "active_hours {:?} isn't HH:MM-HH:MM, ignoring",
+                    hours
+                ));
+            }
+        }
+        for warning in config.threshold_warnings() {
+            warn(format_args!("{}", warning));
+        }
+        (config, sources)
+    }
+
+    /// The `output_schema` and `pretty` that apply to `event`'s output.
+    pub fn output_settings(&self, event: HookEvent) -> (OutputSchema, bool) {
+        let overrides = match event {
+            HookEvent::Stop => &self.stop,
+            HookEvent::PreCompact => &self.precompact,
+            HookEvent::SessionStart => &EventOutput::default(),
+        };
+        (
+            overrides.output_schema.unwrap_or(self.output_schema),
+            overrides.pretty.unwrap_or(self.pretty),
+        )
+    }
+
+    /// Whether `cwd` matches one of `always_substantial_globs`. `dir/**`
+    /// covers `dir` itself as well as everything under it.
+    pub fn always_substantial(&self, cwd: &str) -> bool {
+        if cwd.is_empty() {
+            return false;
+        }
+        let cwd = canonical(cwd);
+        let cwd = cwd.trim_end_matches('/');
+        let home = self.home.as_deref().unwrap_or("").trim_end_matches('/');
+        self.always_substantial_globs.iter().any(|glob| {
+            let glob = match glob.strip_prefix("~/") {
+                Some(rest) if !home.is_empty() => format!("{}/{}", home, rest),
+                _ => glob.clone(),
+            };
+            glob_match(&glob, cwd) || glob_match(&glob, &format!("{}/", cwd))
+        })
+    }
+
+    /// Thresholds that make the gate meaningless: zero passes every session
+    /// and anything above `THRESHOLD_CEILING` passes none. Reported, not
+    /// rejected, since either may be deliberate. `bash_chars_threshold`
+    /// counts characters, so only zero is suspect there.
+    pub fn threshold_warnings(&self) -> Vec<String> {
+        let thresholds = [
+            (
+                "user_msg_threshold",
+                Some(self.user_msg_threshold as f64),
+                true,
+            ),
+            (
+                "tool_turn_threshold",
+                Some(self.tool_turn_threshold as f64),
+                true,
+            ),
+            ("score_threshold", self.score_threshold, true),
+            (
+                "test_run_threshold",
+                self.test_run_threshold.map(|t| t as f64),
+                true,
+            ),
+            (
+                "error_threshold",
+                self.error_threshold.map(|t| t as f64),
+                true,
+            ),
+            (
+                "bash_chars_threshold",
+                self.bash_chars_threshold.map(|t| t as f64),
+                false,
+            ),
+            (
+                "assistant_turn_threshold",
+                self.assistant_turn_threshold.map(|t| t as f64),
+                true,
+            ),
+            (
+                "research_threshold",
+                self.research_threshold.map(|t| t as f64),
+                true,
+            ),
+            (
+                "distinct_tools_threshold",
+                self.distinct_tools_threshold.map(|t| t as f64),
+                true,
+            ),
+        ];
+        thresholds
+            .iter()
+            .filter_map(|&(key, value, capped)| match value {
+                Some(v) if v <= 0.0 => Some(format!(
+                    "{} is {}, so this gate passes every session",
+                    key, v
+                )),
+                Some(v) if capped && v > THRESHOLD_CEILING as f64 => Some(format!(
+                    "{} is {}, above {}, so this gate will likely never pass",
+                    key, v, THRESHOLD_CEILING
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Config keys set in the pattern's YAML frontmatter. Only flat
+/// `key: value` lines naming a known config key are honored; `require: write`
+/// is shorthand for `require_write_not_edit: true`.
+fn frontmatter_overrides(content: &str, defaults: &Map<String, Value>) -> Map<String, Value> {
+    let mut overrides = Map::new();
+
+    for (key, raw) in frontmatter_fields(content) {
+        let (key, raw) = match key.as_str() {
+            "require" => (
+                "require_write_not_edit".to_string(),
+                (raw == "write").to_string(),
+            ),
+            _ => (key, raw),
+        };
+        if let Some(value) = defaults.get(&key).and_then(|d| coerce_env(&raw, d)) {
+            overrides.insert(key, value);
+        }
+    }
+
+    overrides
+}
+
+/// Top-level scalar `key: value` pairs from leading YAML frontmatter, with
+/// surrounding quotes removed from values.
+fn frontmatter_fields(content: &str) -> Vec<(String, String)> {
+    let mut lines = content.lines();
+    if lines.next().map(str::trim) != Some("---") {
+        return Vec::new();
+    }
+
+    lines
+        .take_while(|line| line.trim() != "---")
+        .filter(|line| !line.starts_with(char::is_whitespace))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| {
+            let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+            (key.trim().to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Used verbatim as the data root in place of `~/Data`, e.g. where `HOME`
+/// is unset.
+pub const ROOT_ENV: &str = "SESSION_REFLECT_ROOT";
+
+/// `$SESSION_REFLECT_ROOT`, else `~/Data`: the tree the hook is active in.
+/// `None` when neither is set, since `/Data` would match nothing useful.
+pub(crate) fn data_root(env: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    if let Some(root) = env(ROOT_ENV).filter(|r| !r.is_empty()) {
+        return Some(root);
+    }
+    env("HOME")
+        .filter(|h| !h.is_empty())
+        .map(|home| format!("{}/Data", home))
+}
+
+/// `path` with symlinks and `..` resolved, or as given if it doesn't exist.
+pub(crate) fn canonical(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// Whether `text` matches `pattern`: `*` is any run of characters other
+/// than `/`, `**` any run at all, and `?` one character other than `/`.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern {
+            [] => text.is_empty(),
+            ['*', '*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+            ['*', rest @ ..] => (0..=text.len())
+                .take_while(|&i| i == 0 || text[i - 1] != '/')
+                .any(|i| matches(rest, &text[i..])),
+            ['?', rest @ ..] => {
+                text.first().is_some_and(|&c| c != '/') && matches(rest, &text[1..])
+            }
+            [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches(&pattern, &text)
+}
+
+/// `HH:MM-HH:MM` as seconds since midnight at either end.
+pub(crate) fn parse_active_hours(text: &str) -> Option<(u64, u64)> {
+    let time = |t: &str| -> Option<u64> {
+        let (h, m) = t.trim().split_once(':')?;
+        let (h, m): (u64, u64) = (h.parse().ok()?, m.parse().ok()?);
+        (h < 24 && m < 60).then_some(h * 3600 + m * 60)
+    };
+    let (start, end) = text.split_once('-')?;
+    Some((time(start)?, time(end)?))
+}
+
+/// Nearest of `cwd` and its ancestors, no higher than `data_root`, that has
+/// a `Memory/` directory.
+fn discover_memory_root(cwd: &str, data_root: &str) -> Option<PathBuf> {
+    let cwd = PathBuf::from(canonical(cwd));
+    let data_root = PathBuf::from(canonical(data_root));
+    if cwd.as_os_str().is_empty() || !cwd.starts_with(&data_root) {
+        return None;
+    }
+    cwd.ancestors()
+        .take_while(|dir| dir.starts_with(&data_root))
+        .find(|dir| dir.join("Memory").is_dir())
+        .map(Path::to_path_buf)
+}
+
+/// `SESSION_REFLECT_CONFIG` if set, else `config.json` in the plugin root.
+fn config_file_path(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    if let Some(path) = env("SESSION_REFLECT_CONFIG") {
+        return Some(path.into());
+    }
+    env("CLAUDE_PLUGIN_ROOT").map(|root| Path::new(&root).join("config.json"))
+}
+
+/// Interpret a raw env string according to the shape of the default value.
+fn coerce_env(raw: &str, default: &Value) -> Option<Value> {
+    let raw = raw.trim();
+    match default {
+        Value::Bool(_) => match raw.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Some(Value::Bool(true)),
+            "0" | "false" | "no" | "off" | "" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        Value::Number(_) => serde_json::from_str::<serde_json::Number>(raw)
+            .ok()
+            .map(Value::Number),
+        Value::String(_) => Some(Value::String(raw.to_string())),
+        _ => serde_json::from_str(raw)
+            .ok()
+            .or_else(|| Some(Value::String(raw.to_string()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("/work/*/api", "/work/acme/api"));
+        assert!(!glob_match("/work/*/api", "/work/acme/sub/api"));
+        assert!(glob_match("/work/**/api", "/work/acme/sub/api"));
+        assert!(glob_match("/work/critical/**", "/work/critical/"));
+        assert!(glob_match("/work/proj-?", "/work/proj-a"));
+        assert!(!glob_match("/work/proj-?", "/work/proj-ab"));
+        assert!(!glob_match("/work/critical", "/work/critical-notes"));
+    }
+
+    #[test]
+    fn test_env_overrides_task_weight() {
+        let env = |key: &str| match key {
+            "SESSION_REFLECT_TASK_WEIGHT" => Some("5".to_string()),
+            _ => None,
+        };
+        assert_eq!(Config::load("", &env).task_weight, 5);
+    }
+
+    #[test]
+    fn test_frontmatter_thresholds_honored() {
+        let home = TempDir::new("frontmatter");
+        let pattern = home.path().join(PATTERN_PATH);
+        fs::create_dir_all(pattern.parent().unwrap()).unwrap();
+        fs::write(
+            &pattern,
+            "---\ntitle: Session Reflect\ntool_turn_threshold: 3\nrequire: write\n---\n# Session Reflect\n\nReflect.\n",
+        )
+        .unwrap();
+        let cwd = home.path().to_string_lossy().into_owned();
+
+        let config = Config::load(&cwd, &|_| None);
+        assert_eq!(config.tool_turn_threshold, 3);
+        assert_eq!(config.user_msg_threshold, USER_MSG_THRESHOLD);
+        assert!(config.require_write_not_edit);
+
+        let env =
+            |key: &str| (key == "SESSION_REFLECT_TOOL_TURN_THRESHOLD").then(|| "7".to_string());
+        assert_eq!(Config::load(&cwd, &env).tool_turn_threshold, 7);
+    }
+
+    #[test]
+    fn test_invalid_value_drops_only_its_key() {
+        let home = TempDir::new("config-invalid-key");
+        let pattern = home.path().join(PATTERN_PATH);
+        fs::create_dir_all(pattern.parent().unwrap()).unwrap();
+        fs::write(&pattern, "---\nuser_msg_threshold: 2\n---\nReflect.\n").unwrap();
+        let file = home.path().join("config.json");
+        fs::write(&file, r#"{"task_weight": 3, "output_schema": "v9"}"#).unwrap();
+        let cwd = home.path().to_string_lossy().into_owned();
+
+        let env = |key: &str| match key {
+            "SESSION_REFLECT_CONFIG" => Some(file.to_string_lossy().into_owned()),
+            "SESSION_REFLECT_TOOL_TURN_THRESHOLD" => Some("-1".to_string()),
+            "SESSION_REFLECT_TEST_RUN_THRESHOLD" => Some("abc".to_string()),
+            _ => None,
+        };
+        let (config, sources) = Config::load_with_sources(&cwd, &env);
+        assert_eq!(config.user_msg_threshold, 2);
+        assert_eq!(sources["user_msg_threshold"], "frontmatter");
+        assert_eq!(config.task_weight, 3);
+        assert_eq!(sources["task_weight"], "file");
+        assert_eq!(config.output_schema, Config::default().output_schema);
+        assert_eq!(sources["output_schema"], "default");
+        assert_eq!(config.tool_turn_threshold, TOOL_TURN_THRESHOLD);
+        assert_eq!(config.test_run_threshold, None);
+        assert_eq!(sources["test_run_threshold"], "default");
+    }
+
+    #[test]
+    fn test_pattern_env_lists_candidates() {
+        let home = TempDir::new("pattern-env");
+        let pattern = home.path().join("Vault/Reflect.md");
+        fs::create_dir_all(pattern.parent().unwrap()).unwrap();
+        fs::write(&pattern, "---\ntool_turn_threshold: 3\n---\nReflect.\n").unwrap();
+        let cwd = home.path().to_string_lossy().into_owned();
+
+        let env =
+            |key: &str| (key == PATTERN_ENV).then(|| "Missing.md:Vault/Reflect.md".to_string());
+        let config = Config::load(&cwd, &env);
+        assert_eq!(config.pattern_paths, ["Missing.md", "Vault/Reflect.md"]);
+        assert_eq!(config.tool_turn_threshold, 3);
+    }
+
+    #[test]
+    fn test_unknown_turn_entry_types_ignored() {
+        let env = |key: &str| {
+            (key == "SESSION_REFLECT_TURN_ENTRY_TYPES")
+                .then(|| r#"{"turn":["assistant","system","bogus"]}"#.to_string())
+        };
+        let types = Config::load("", &env).turn_entry_types;
+        assert_eq!(types.turn, ["assistant", "system"]);
+        assert_eq!(types.user, TurnEntryTypes::default().user);
+    }
+
+    #[test]
+    fn test_degenerate_thresholds_warn() {
+        assert!(Config::default().threshold_warnings().is_empty());
+
+        let config = Config {
+            user_msg_threshold: 0,
+            tool_turn_threshold: 1_000_000,
+            ..Config::default()
+        };
+        let warnings = config.threshold_warnings();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("user_msg_threshold is 0"));
+        assert!(warnings[1].starts_with("tool_turn_threshold is 1000000"));
+
+        let config = Config {
+            score_threshold: Some(0.0),
+            bash_chars_threshold: Some(0),
+            ..Config::default()
+        };
+        let warnings = config.threshold_warnings();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("score_threshold is 0,"));
+        assert!(warnings[1].starts_with("bash_chars_threshold is 0,"));
+
+        let long_scripts = Config {
+            bash_chars_threshold: Some(50_000),
+            ..Config::default()
+        };
+        assert!(long_scripts.threshold_warnings().is_empty());
+    }
+
+    #[test]
+    fn test_discovers_memory_root_at_grandparent() {
+        let home = TempDir::new("memory-root");
+        let vault = home.path().join("Data/vault");
+        let cwd = vault.join("projects/app");
+        fs::create_dir_all(vault.join("Memory")).unwrap();
+        fs::create_dir_all(&cwd).unwrap();
+        let env = |key: &str| match key {
+            "HOME" => Some(home.path().to_string_lossy().into_owned()),
+            "SESSION_REFLECT_DISCOVER_MEMORY_ROOT" => Some("1".to_string()),
+            _ => None,
+        };
+
+        let config = Config::load(&cwd.to_string_lossy(), &env);
+        assert_eq!(config.memory_root, Some(fs::canonicalize(&vault).unwrap()));
+
+        // Nothing is searched above the data root
+        let outside = home.path().join("elsewhere");
+        fs::create_dir_all(&outside).unwrap();
+        assert_eq!(
+            Config::load(&outside.to_string_lossy(), &env).memory_root,
+            None
+        );
+    }
+
+    #[test]
+    fn test_env_selects_output_schema() {
+        let env = |key: &str| (key == "SESSION_REFLECT_OUTPUT_SCHEMA").then(|| "v2".to_string());
+        assert_eq!(Config::load("", &env).output_schema, OutputSchema::V2);
+    }
+}
@@ -0,0 +1,2347 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::LazyLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::analysis::{analyze_session, open_transcript, Analysis, Sentinel};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{
+    canonical, data_root, parse_active_hours, Config, InterpolateEnv, OutsideHours, ROOT_ENV,
+};
+use crate::pattern::{
+    find_reflection_pattern, interpolate_env, load_pattern, render_template, resolve_wikilinks,
+    unescape_separator,
+};
+use crate::state::{self, BlockRecord};
+use crate::syslog::{Syslog, SystemLog};
+use crate::{debug, env_var, warn};
+
+pub const FALLBACK_REASON: &str =
+    "Substantial session with no learnings captured. Create a file in Memory/Learnings/ or Memory/Decisions/ before ending.";
+
+pub const PRECOMPACT_PREFIX: &str =
+    "BEFORE COMPACTING — capture session learnings and decisions now. ";
+
+/// Default `session_start_messages` entry for a resumed session.
+pub const SESSION_START_RESUME: &str =
+    "Resuming an earlier session — pick up where you left off, and capture learnings and decisions as you go.";
+
+/// Injected instead of the full prompt during `precompact_cooldown_secs`.
+pub const PRECOMPACT_REMINDER: &str =
+    "BEFORE COMPACTING — capture any new learnings or decisions since the last reminder.";
+
+/// Set to `1` to bypass the `stop_hook_active` guard, so tests can drive a
+/// re-invoked Stop to a block. Honored by debug builds only: a release
+/// binary always lets a hook-triggered Stop through, and can't loop.
+pub const IGNORE_STOP_ACTIVE_ENV: &str = "SESSION_REFLECT_IGNORE_STOP_ACTIVE";
+
+/// How long `notify_command` may run before it's killed.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long `include_git_log` waits for `git log`.
+const GIT_LOG_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Combined JSON payload supporting both Stop and PreCompact hook events.
+/// Unknown fields are silently ignored by serde.
+#[derive(Deserialize, Default, Debug, Clone)]
+pub struct HookInput {
+    /// Stop-specific: true when the hook itself triggered this invocation.
+    #[serde(default)]
+    pub stop_hook_active: bool,
+    #[serde(default)]
+    pub cwd: String,
+    /// Present in Stop hooks; may be absent in PreCompact.
+    #[serde(default)]
+    pub transcript_path: String,
+    /// PreCompact-specific: "manual" or "auto".
+    #[serde(default)]
+    pub trigger: Option<String>,
+    /// Sent by Claude Code with every event; only `SessionStart` is needed
+    /// to tell events apart, since it carries neither field above.
+    #[serde(default)]
+    pub hook_event_name: Option<String>,
+    /// SessionStart-specific: "startup", "resume", "clear" or "compact".
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Sent with every event. A transcript holding entries from several
+    /// sessions is counted for this one only.
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
+impl HookInput {
+    /// PreCompact payloads carry a `trigger`; everything else that isn't a
+    /// named SessionStart is a Stop.
+    pub fn event(&self) -> HookEvent {
+        if self.hook_event_name.as_deref() == Some("SessionStart") {
+            HookEvent::SessionStart
+        } else if self.trigger.is_some() {
+            HookEvent::PreCompact
+        } else {
+            HookEvent::Stop
+        }
+    }
+}
+
+/// Hook event a decision answers, as Claude Code names it.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum HookEvent {
+    Stop,
+    PreCompact,
+    SessionStart,
+}
+
+/// Outcome of a hook invocation.
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    /// Let the session continue; nothing is printed.
+    Allow,
+    /// Stop: refuse to end the session until reflection happens.
+    Block { reason: String, code: ReasonCode },
+    /// Add reflection context without blocking (PreCompact).
+    Inject {
+        event: HookEvent,
+        context: String,
+        code: ReasonCode,
+    },
+}
+
+/// Stable, machine-readable classification of why a block or injection
+/// happened, emitted as `reason_code` under the v2 schema.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReasonCode {
+    /// A substantial Stop with no memory captured.
+    SubstantialNoMemory,
+    /// As above, once `block_after_streak` sessions in a row went uncaptured.
+    StreakThreshold,
+    /// A `[[session-reflect:block]]` sentinel forced the block.
+    SentinelBlock,
+    /// A block softened to context outside `active_hours`.
+    OutsideActiveHours,
+    /// `always_inject_on_stop` on a Stop that wasn't blocked.
+    AlwaysInject,
+    /// The full PreCompact prompt.
+    Precompact,
+    /// The PreCompact reminder within `precompact_cooldown_secs`.
+    PrecompactReminder,
+    /// A `session_start_messages` entry.
+    SessionStart,
+}
+
+impl Decision {
+    /// Exit status contract. By default every decision exits 0 and the
+    /// outcome travels in stdout JSON alone — Claude Code treats exit 2 as a
+    /// blocking error and reads stderr instead, so mapped codes are opt-in
+    /// via `exit_codes` (block → 2, inject → 0, allow → 0).
+    pub fn exit_code(&self, exit_codes: bool) -> u8 {
+        match self {
+            Decision::Block { .. } if exit_codes => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// The syslog `Context::process` sends to.
+static SYSLOG: LazyLock<Syslog> = LazyLock::new(Syslog::default);
+
+/// Process-level inputs `decide` reads besides the payload and config, so
+/// tests can substitute them.
+pub struct Context<'a> {
+    pub env: &'a dyn Fn(&str) -> Option<String>,
+    pub clock: &'a dyn Clock,
+    pub syslog: &'a dyn SystemLog,
+}
+
+impl Context<'static> {
+    /// The real environment, wall clock and syslog.
+    pub fn process() -> Self {
+        Context {
+            env: &env_var,
+            clock: &SystemClock,
+            syslog: &*SYSLOG,
+        }
+    }
+}
+
+/// How a Stop evaluates against the thresholds and the capture check.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Outcome {
+    /// Below the substance thresholds.
+    Light,
+    /// Substantial, with memory captured.
+    Captured,
+    /// Substantial with nothing captured — the case that blocks.
+    Uncaptured,
+}
+
+/// What the transcript alone says about a Stop, before the state-backed
+/// gates (capture gap, lookback, warmup, streak, dedupe) have their say.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Verdict {
+    /// Never enforced: an empty transcript, the allow sentinel, or a
+    /// declined reflection.
+    Excused,
+    /// The block sentinel: blocks whatever the analysis says.
+    Forced,
+    /// Down to the thresholds and the capture check.
+    Assessed(Outcome),
+}
+
+impl Verdict {
+    /// The outcome tallied for this verdict when there's no state to
+    /// consult, as in `replay` and `analyze`.
+    pub fn outcome(self) -> Outcome {
+        match self {
+            Verdict::Excused => Outcome::Light,
+            Verdict::Forced => Outcome::Uncaptured,
+            Verdict::Assessed(outcome) => outcome,
+        }
+    }
+}
+
+/// The transcript-only part of a Stop decision: sentinels, a declined
+/// reflection, and `always_substantial_globs` for `cwd`, then
+/// `stop_outcome`.
+pub fn stop_verdict(analysis: &Analysis, cwd: &str, config: &Config) -> Verdict {
+    // An empty or blank-only file: nothing happened yet
+    if analysis.entries == 0 {
+        debug(config, format_args!("empty transcript"));
+        return Verdict::Excused;
+    }
+
+    // A sentinel typed into the conversation overrides the analysis
+    match analysis.sentinel {
+        Some(Sentinel::Allow) => Verdict::Excused,
+        Some(Sentinel::Block) => Verdict::Forced,
+        // The user said in so many words that there's nothing to capture
+        None if analysis.declined => {
+            debug(
+                config,
+                format_args!("reflection declined in the conversation"),
+            );
+            Verdict::Excused
+        }
+        None if config.always_substantial(cwd) => {
+            debug(config, format_args!("cwd matches always_substantial_globs"));
+            Verdict::Assessed(strict_outcome(analysis, config))
+        }
+        None => Verdict::Assessed(stop_outcome(analysis, config)),
+    }
+}
+
+pub fn decide(input: &HookInput, config: &Config, ctx: &Context) -> Decision {
+    let mut analysis = None;
+    let decision = decide_event(input, config, ctx, &mut analysis);
+    if config.log_syslog {
+        log_decision(&decision, input, analysis.as_ref(), config, ctx);
+    }
+    decision
+}
+
+/// With `log_syslog`, one `key=value` line per decision with the counts
+/// behind it — those of the transcript the decision analyzed, if any —
+/// best-effort.
+fn log_decision(
+    decision: &Decision,
+    input: &HookInput,
+    analysis: Option<&Analysis>,
+    config: &Config,
+    ctx: &Context,
+) {
+    let label = |value: serde_json::Value| value.as_str().unwrap_or_default().to_string();
+    let (verdict, code) = match decision {
+        Decision::Allow => ("allow", None),
+        Decision::Block { code, .. } => ("block", Some(code)),
+        Decision::Inject { code, .. } => ("inject", Some(code)),
+    };
+    let mut line = format!(
+        "event={} decision={}",
+        label(serde_json::json!(input.event())),
+        verdict
+    );
+    if let Some(code) = code {
+        line.push_str(&format!(" code={}", label(serde_json::json!(code))));
+    }
+    if let Some(analysis) = analysis {
+        line.push_str(&format!(
+            " user_messages={} tool_turns={} memory_written={}",
+            analysis.user_messages,
+            analysis.effective_tool_turns(config),
+            analysis.captured(config)
+        ));
+    }
+    line.push_str(&format!(" cwd={:?}", input.cwd));
+    if let Err(e) = ctx.syslog.send(&line) {
+        debug(config, format_args!("syslog: {}", e));
+    }
+}
+
+/// Decide `input`, leaving the analysis it was decided on, if any, in
+/// `analysis`.
+fn decide_event(
+    input: &HookInput,
+    config: &Config,
+    ctx: &Context,
+    analysis: &mut Option<Analysis>,
+) -> Decision {
+    let event = input.event();
+    let is_pre_compact = event == HookEvent::PreCompact;
+
+    // Guard: prevent infinite loop (Stop only)
+    if !is_pre_compact && input.stop_hook_active && !ignore_stop_active(ctx) {
+        return Decision::Allow;
+    }
+
+    // Guard: only fire inside ~/Data
+    let root = match data_root(ctx.env) {
+        Some(root) => root,
+        None => {
+            warn(format_args!(
+                "HOME is unset and {} isn't set, so ~/Data can't be resolved; allowing",
+                ROOT_ENV
+            ));
+            return Decision::Allow;
+        }
+    };
+    // Whole components only: `~/Database` isn't inside `~/Data`
+    if !Path::new(&canonical(&input.cwd)).starts_with(canonical(&root)) {
+        return Decision::Allow;
+    }
+
+    // SessionStart: a message chosen by how the session started, or nothing
+    if event == HookEvent::SessionStart {
+        let source = input.source.as_deref().unwrap_or("startup");
+        return match config.session_start_messages.get(source) {
+            Some(message) if !message.trim().is_empty() => Decision::Inject {
+                event,
+                context: message.clone(),
+                code: ReasonCode::SessionStart,
+            },
+            _ => Decision::Allow,
+        };
+    }
+
+    let discovered;
+    let input = match discover_transcript(input, config, ctx) {
+        Some(path) => {
+            debug(
+                config,
+                format_args!("discovered transcript {}", path.display()),
+            );
+            discovered = HookInput {
+                transcript_path: path.to_string_lossy().into_owned(),
+                ..input.clone()
+            };
+            &discovered
+        }
+        None => input,
+    };
+    *analysis = load_analysis(&input.transcript_path, input.session_id.as_deref(), config);
+    let analysis = analysis.as_ref();
+
+    // For PreCompact: compaction implies substantial session.
+    // Always inject the reflection prompt — let the AI decide whether
+    // additional capture is needed, even if some memory was already written.
+    if is_pre_compact {
+        // The transcript is optional here; when it's readable, it personalizes the prompt.
+        if config.precompact_skip_if_recent_memory && analysis.is_some_and(|a| a.captured(config)) {
+            return Decision::Allow;
+        }
+        let manual = input.trigger.as_deref() == Some("manual");
+        let (context, code) = if !manual && in_precompact_cooldown(&input.cwd, config, ctx) {
+            (
+                PRECOMPACT_REMINDER.to_string(),
+                ReasonCode::PrecompactReminder,
+            )
+        } else {
+            // Only the full prompt needs the pattern (and `git log`)
+            let (reason, _) = reflection_prompt(&input.cwd, config, analysis, ctx.env);
+            (
+                format!("{}{}", PRECOMPACT_PREFIX, reason),
+                ReasonCode::Precompact,
+            )
+        };
+
+        return Decision::Inject {
+            event: HookEvent::PreCompact,
+            context,
+            code,
+        };
+    }
+
+    // --- Stop hook path (existing behavior) ---
+
+    let decision = match decide_stop(input, analysis, config, ctx) {
+        Decision::Block { reason, code } if outside_active_hours(config, ctx) => {
+            debug(config, format_args!("outside active_hours"));
+            match config.outside_hours {
+                OutsideHours::Block => Decision::Block { reason, code },
+                OutsideHours::Inject => Decision::Inject {
+                    event: HookEvent::Stop,
+                    context: reason,
+                    code: ReasonCode::OutsideActiveHours,
+                },
+                OutsideHours::Allow => Decision::Allow,
+            }
+        }
+        decision => decision,
+    };
+
+    if let (Decision::Block { reason, .. }, Some(command)) = (&decision, &config.notify_command) {
+        notify(command, reason, input, config);
+    }
+
+    // Ritual projects see the prompt at the end of every session, even
+    // ones the thresholds let through
+    if decision == Decision::Allow && config.always_inject_on_stop {
+        let (context, _) = reflection_prompt(&input.cwd, config, analysis, ctx.env);
+        return Decision::Inject {
+            event: HookEvent::Stop,
+            context,
+            code: ReasonCode::AlwaysInject,
+        };
+    }
+
+    decision
+}
+
+fn ignore_stop_active(ctx: &Context) -> bool {
+    cfg!(debug_assertions) && (ctx.env)(IGNORE_STOP_ACTIVE_ENV).is_some_and(|v| v.trim() == "1")
+}
+
+/// Run `notify_command` through `sh -c` for a block, best-effort: output is
+/// discarded, and it's killed after `NOTIFY_TIMEOUT`. The reason and `cwd`
+/// are passed as `SESSION_REFLECT_REASON` and `SESSION_REFLECT_CWD`.
+fn notify(command: &str, reason: &str, input: &HookInput, config: &Config) {
+    let child = Command::new("sh")
+        .args(["-c", command])
+        .env("SESSION_REFLECT_REASON", reason)
+        .env("SESSION_REFLECT_CWD", &input.cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(c) => c,
+        Err(e) => {
+            debug(
+                config,
+                format_args!("notify_command failed to start: {}", e),
+            );
+            return;
+        }
+    };
+
+    let start = Instant::now();
+    while start.elapsed() < NOTIFY_TIMEOUT {
+        match child.try_wait() {
+            Ok(Some(_)) | Err(_) => return,
+            Ok(None) => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    debug(config, format_args!("notify_command timed out"));
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+fn decide_stop(
+    input: &HookInput,
+    analysis: Option<&Analysis>,
+    config: &Config,
+    ctx: &Context,
+) -> Decision {
+    let analysis = match analysis {
+        Some(a) => a,
+        None => return Decision::Allow,
+    };
+
+    let code = match stop_verdict(analysis, &input.cwd, config) {
+        Verdict::Excused => return Decision::Allow,
+        Verdict::Forced => ReasonCode::SentinelBlock,
+        Verdict::Assessed(outcome) => {
+            // Captured within the last few hours of work → not due again yet
+            if within_capture_gap(input, outcome, config, ctx) {
+                debug(
+                    config,
+                    format_args!("memory captured within max_capture_gap_hours"),
+                );
+                return Decision::Allow;
+            }
+            // Captured in a recent session → capture is a habit here, not a per-session chore
+            if outcome == Outcome::Uncaptured && captured_in_lookback(input, config, ctx) {
+                debug(
+                    config,
+                    format_args!("memory captured within lookback_sessions"),
+                );
+                return Decision::Allow;
+            }
+            // One of a new project's first sessions → don't enforce yet
+            if outcome == Outcome::Uncaptured && in_warmup(input, config, ctx) {
+                debug(config, format_args!("within warmup_sessions"));
+                return Decision::Allow;
+            }
+            if outcome == Outcome::Captured {
+                graduate_warmup(input, config, ctx);
+            }
+            // Not yet enough uncaptured sessions in a row → let this one go
+            if !streak_reached(input, outcome, config, ctx) {
+                debug(config, format_args!("below block_after_streak"));
+                return Decision::Allow;
+            }
+            // Not substantial, or substantial with memory writes → allow stop
+            if outcome != Outcome::Uncaptured {
+                return Decision::Allow;
+            }
+            if config.block_after_streak > 1 {
+                ReasonCode::StreakThreshold
+            } else {
+                ReasonCode::SubstantialNoMemory
+            }
+        }
+    };
+
+    // Another window already blocked on this exact transcript → don't double-nudge
+    if is_duplicate_block(analysis, config, ctx) {
+        debug(config, format_args!("duplicate block suppressed"));
+        return Decision::Allow;
+    }
+
+    // Substantial + no memory writes → block and prompt reflection
+    let gates = triggered_gates(analysis, config);
+    debug(config, format_args!("gates: {:?}", gates));
+
+    let (mut reason, pattern) = reflection_prompt(&input.cwd, config, Some(analysis), ctx.env);
+    if config.verbose_reasons {
+        for gate in &gates {
+            reason.push_str(&format!("\n- {}", gate));
+        }
+    }
+    if config.penalize_memory_deletion && !analysis.memory_deletions.is_empty() {
+        reason.push_str(&format!(
+            "\n\nMemory files deleted this session: {}. Restore them or capture anew.",
+            analysis.memory_deletions.join(", ")
+        ));
+    }
+    if let Some(pattern) = pattern.filter(|_| config.include_pattern_path) {
+        let pattern = fs::canonicalize(&pattern).unwrap_or(pattern);
+        reason.push_str(&format!("\n\nSee: {}", pattern.display()));
+    }
+    if let Some(max) = config.max_reason_chars {
+        reason = truncate_chars(&reason, max);
+    }
+
+    Decision::Block { reason, code }
+}
+
+/// `text` cut to `max` characters, the last of them `…`, when it's longer.
+/// Below 2 there's no room for the ellipsis, so the cut is bare.
+fn truncate_chars(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let cut = |chars: usize| text.char_indices().nth(chars).map_or(0, |(i, _)| i);
+    match max {
+        0 | 1 => text[..cut(max)].to_string(),
+        _ => format!("{}…", &text[..cut(max - 1)]),
+    }
+}
+
+/// The pattern's prompt, or the built-in fallback, with `analysis` (if any)
+/// choosing a `patterns` entry and filling in the template vars, then `env`
+/// filling in `${VAR}`s under `interpolate_env`. Also the pattern file the
+/// prompt came from, `None` for the fallback.
+fn reflection_prompt(
+    cwd: &str,
+    config: &Config,
+    analysis: Option<&Analysis>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> (String, Option<PathBuf>) {
+    let work_type = analysis.and_then(|analysis| analysis.pattern_work_type(config));
+    let found = find_reflection_pattern(cwd, &config.pattern_paths, &config.patterns, work_type);
+    let (path, prompt) = match found {
+        Some((path, prompt)) => (Some(path), prompt),
+        None => (None, FALLBACK_REASON.to_string()),
+    };
+    let mut bodies = vec![prompt];
+    bodies.extend(
+        config
+            .extra_patterns
+            .iter()
+            .filter_map(|path| load_pattern(&Path::new(cwd).join(path))),
+    );
+    let prompt = bodies.join(&unescape_separator(&config.pattern_separator));
+    let prompt = if config.resolve_wikilinks {
+        resolve_wikilinks(&prompt)
+    } else {
+        prompt
+    };
+    let vars = match analysis {
+        Some(analysis) => analysis.template_vars(config),
+        None => Analysis::unknown_template_vars(),
+    };
+    let prompt = render_template(&prompt, &vars);
+    let prompt = match config.interpolate_env {
+        InterpolateEnv::Off => prompt,
+        InterpolateEnv::Keep => interpolate_env(&prompt, env, false),
+        InterpolateEnv::Blank => interpolate_env(&prompt, env, true),
+    };
+    let prompt = match git_log(cwd, config) {
+        Some(log) => format!("{}\n\nRecent commits:\n{}", prompt, log),
+        None => prompt,
+    };
+    (prompt, path)
+}
+
+/// `git log --oneline` for `cwd`, or `None` if it isn't a repo, has no
+/// commits, or git fails or outlasts `GIT_LOG_TIMEOUT`.
+fn git_log(cwd: &str, config: &Config) -> Option<String> {
+    if !config.include_git_log || cwd.is_empty() {
+        return None;
+    }
+    let mut child = Command::new("git")
+        .args(["-C", cwd, "log", "--oneline", "--no-color", "-n"])
+        .arg(config.git_log_lines.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => break,
+            Ok(None) if start.elapsed() < GIT_LOG_TIMEOUT => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Ok(None) => {
+                debug(config, format_args!("git log timed out"));
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            _ => return None,
+        }
+    }
+    let output = child.wait_with_output().ok()?;
+    let log = String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string();
+    (!log.is_empty()).then_some(log)
+}
+
+/// With `discover_transcript`, when the payload's transcript is missing or
+/// unreadable: the most recently modified one in `project_transcripts`. A
+/// `transcript_path` naming a directory is searched for its newest `.jsonl`
+/// first; without `discover_transcript` it only draws a warning.
+fn discover_transcript(input: &HookInput, config: &Config, ctx: &Context) -> Option<PathBuf> {
+    let path = Path::new(&input.transcript_path);
+    let is_dir = !input.transcript_path.is_empty() && path.is_dir();
+    if is_dir && !config.discover_transcript {
+        warn(format_args!(
+            "transcript_path {} is a directory, not a transcript; \
+             set discover_transcript to use the newest .jsonl inside it",
+            path.display()
+        ));
+        return None;
+    }
+    if !config.discover_transcript
+        || (!input.transcript_path.is_empty() && !is_dir && fs::File::open(path).is_ok())
+    {
+        return None;
+    }
+    let inside = if is_dir {
+        jsonl_newest_first(path)
+    } else {
+        Vec::new()
+    };
+    inside
+        .into_iter()
+        .next()
+        .or_else(|| project_transcripts(input, ctx).into_iter().next())
+}
+
+/// The `.jsonl` files in Claude's directory for the project
+/// (`CLAUDE_PROJECT_DIR`, else `cwd`), newest first. That's
+/// `~/.claude/projects/<path with each non-alphanumeric char as '-'>/`, with
+/// `CLAUDE_CONFIG_DIR` in place of `~/.claude` if set.
+fn project_transcripts(input: &HookInput, ctx: &Context) -> Vec<PathBuf> {
+    let claude_dir = match (ctx.env)("CLAUDE_CONFIG_DIR")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| (ctx.env)("HOME").map(|home| Path::new(&home).join(".claude")))
+    {
+        Some(d) => d,
+        None => return Vec::new(),
+    };
+    let project = (ctx.env)("CLAUDE_PROJECT_DIR")
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| input.cwd.clone());
+    let encoded: String = project
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+
+    jsonl_newest_first(&claude_dir.join("projects").join(encoded))
+}
+
+/// The `.jsonl` files directly in `dir`, most recently modified first.
+fn jsonl_newest_first(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut transcripts: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "jsonl"))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    transcripts.sort_by(|a, b| b.cmp(a));
+    transcripts.into_iter().map(|(_, path)| path).collect()
+}
+
+/// With `lookback_sessions`, whether one of that many of the project's most
+/// recent other transcripts captured memory.
+fn captured_in_lookback(input: &HookInput, config: &Config, ctx: &Context) -> bool {
+    if config.lookback_sessions == 0 {
+        return false;
+    }
+    let current = canonical(&input.transcript_path);
+    project_transcripts(input, ctx)
+        .iter()
+        .filter(|path| canonical(&path.to_string_lossy()) != current)
+        .take(config.lookback_sessions)
+        .filter_map(|path| load_analysis(&path.to_string_lossy(), None, config))
+        .any(|analysis| analysis.captured(config))
+}
+
+/// Analyze the transcript at `path`, if there is one to open, counting only
+/// `session_id`'s entries when given.
+fn load_analysis(path: &str, session_id: Option<&str>, config: &Config) -> Option<Analysis> {
+    if path.is_empty() {
+        return None;
+    }
+    let transcript = open_transcript(Path::new(path), config).ok()?;
+    let analysis = analyze_session(transcript, session_id, config);
+    debug(config, format_args!("{:?}", analysis));
+    for write in &analysis.memory_writes {
+        let path = write.path.as_deref().unwrap_or("(capture tool)");
+        debug(
+            config,
+            format_args!("memory write credited: {} {}", write.tool, path),
+        );
+    }
+    Some(analysis)
+}
+
+/// Whether the clock's local time falls outside a valid `active_hours`.
+fn outside_active_hours(config: &Config, ctx: &Context) -> bool {
+    let (start, end) = match config.active_hours.as_deref().and_then(parse_active_hours) {
+        Some(window) => window,
+        None => return false,
+    };
+    let now = ctx.clock.local_time_of_day();
+    let inside = match start.cmp(&end) {
+        Ordering::Less => start <= now && now < end,
+        // Wraps past midnight, e.g. 22:00-06:00
+        Ordering::Greater => now >= start || now < end,
+        Ordering::Equal => true,
+    };
+    !inside
+}
+
+/// Whether a full PreCompact prompt went out for `cwd` within
+/// `precompact_cooldown_secs`; if not, this one starts a new window.
+fn in_precompact_cooldown(cwd: &str, config: &Config, ctx: &Context) -> bool {
+    let window = config.precompact_cooldown_secs;
+    if window == 0 {
+        return false;
+    }
+    let dir = match state::state_dir(ctx.env) {
+        Some(d) => d,
+        None => return false,
+    };
+
+    let now = ctx.clock.now();
+    state::update_best_effort(&dir, |state| {
+        state
+            .last_precompact
+            .retain(|_, at| now.saturating_sub(*at) < window);
+        if state.last_precompact.contains_key(cwd) {
+            return true;
+        }
+        state.last_precompact.insert(cwd.to_string(), now);
+        false
+    })
+}
+
+/// Project key for per-project state: the discovered vault, else `cwd`.
+fn project_key(input: &HookInput, config: &Config) -> String {
+    match &config.memory_root {
+        Some(root) => root.to_string_lossy().into_owned(),
+        None => input.cwd.clone(),
+    }
+}
+
+/// With `max_capture_gap_hours`, record a captured Stop's time for the
+/// project, and report whether an uncaptured one falls within the gap since.
+fn within_capture_gap(input: &HookInput, outcome: Outcome, config: &Config, ctx: &Context) -> bool {
+    let gap = match config.max_capture_gap_hours {
+        Some(hours) if outcome != Outcome::Light => hours.saturating_mul(3600),
+        _ => return false,
+    };
+    let dir = match state::state_dir(ctx.env) {
+        Some(d) => d,
+        None => return false,
+    };
+    let now = ctx.clock.now();
+
+    state::update_best_effort(&dir, |state| {
+        let key = project_key(input, config);
+        if outcome == Outcome::Captured {
+            state.last_capture.insert(key, now);
+            return false;
+        }
+        state
+            .last_capture
+            .get(&key)
+            .is_some_and(|&at| now.saturating_sub(at) <= gap)
+    })
+}
+
+/// Whether this uncaptured session is one of the project's first
+/// `warmup_sessions` substantial ones, which aren't enforced.
+fn in_warmup(input: &HookInput, config: &Config, ctx: &Context) -> bool {
+    if config.warmup_sessions == 0 {
+        return false;
+    }
+    let dir = match state::state_dir(ctx.env) {
+        Some(d) => d,
+        None => return false,
+    };
+
+    state::update_best_effort(&dir, |state| {
+        let warmup = state.warmup.entry(project_key(input, config)).or_default();
+        if warmup.graduated {
+            return false;
+        }
+        let seen = warmup.sessions.contains(&input.transcript_path);
+        if !seen && warmup.sessions.len() < config.warmup_sessions {
+            warmup.sessions.push(input.transcript_path.clone());
+            return true;
+        }
+        seen
+    })
+}
+
+/// A memory write ends the project's warmup early.
+fn graduate_warmup(input: &HookInput, config: &Config, ctx: &Context) {
+    if config.warmup_sessions == 0 {
+        return;
+    }
+    if let Some(dir) = state::state_dir(ctx.env) {
+        state::update_best_effort(&dir, |state| {
+            state
+                .warmup
+                .entry(project_key(input, config))
+                .or_default()
+                .graduated = true;
+        });
+    }
+}
+
+/// Track consecutive uncaptured sessions per project and report whether
+/// the streak has reached `block_after_streak`. Sessions are keyed by
+/// transcript, so the many Stops of one session count once, and no more
+/// than `block_after_streak` are kept; a captured session resets the streak.
+fn streak_reached(input: &HookInput, outcome: Outcome, config: &Config, ctx: &Context) -> bool {
+    if config.block_after_streak <= 1 || outcome == Outcome::Light {
+        return true;
+    }
+    let dir = match state::state_dir(ctx.env) {
+        Some(d) => d,
+        None => return true,
+    };
+
+    state::update_best_effort(&dir, |state| {
+        let streak = state.streaks.entry(project_key(input, config)).or_default();
+        if outcome == Outcome::Captured {
+            streak.clear();
+            return true;
+        }
+        if streak.len() < config.block_after_streak && !streak.contains(&input.transcript_path) {
+            streak.push(input.transcript_path.clone());
+        }
+        streak.len() >= config.block_after_streak
+    })
+}
+
+/// Record this block in the state file, reporting whether an identical
+/// transcript was already blocked within `dedupe_window_secs`. Racing Stop
+/// events serialize on the state lock, so exactly one of them blocks.
+fn is_duplicate_block(analysis: &Analysis, config: &Config, ctx: &Context) -> bool {
+    if config.dedupe_window_secs == 0 {
+        return false;
+    }
+    let dir = match state::state_dir(ctx.env) {
+        Some(d) => d,
+        None => return false,
+    };
+
+    let now = ctx.clock.now();
+    let hash = format!("{:016x}", analysis.content_hash);
+    let window = config.dedupe_window_secs;
+
+    state::update_best_effort(&dir, |state| {
+        state
+            .recent_blocks
+            .retain(|b| now.saturating_sub(b.at) < window);
+        if state.recent_blocks.iter().any(|b| b.hash == hash) {
+            return true;
+        }
+        state.recent_blocks.push(BlockRecord { hash, at: now });
+        false
+    })
+}
+
+/// A substance signal that crossed its threshold.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Gate {
+    /// Stable identifier: `tool_turns`, `test_runs`, `tool_errors`,
+    /// `bash_chars`, `assistant_turns` or `research`.
+    pub id: &'static str,
+    pub value: usize,
+    pub threshold: usize,
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} (threshold {})",
+            self.id, self.value, self.threshold
+        )
+    }
+}
+
+/// Every activity gate the session trips. Any one of them, together with
+/// the user-message threshold, makes a session substantial.
+pub fn triggered_gates(analysis: &Analysis, config: &Config) -> Vec<Gate> {
+    let candidates = [
+        (
+            "tool_turns",
+            analysis.effective_tool_turns(config),
+            Some(config.tool_turn_threshold),
+        ),
+        ("test_runs", analysis.test_runs, config.test_run_threshold),
+        ("tool_errors", analysis.tool_errors, config.error_threshold),
+        (
+            "bash_chars",
+            analysis.bash_chars_total,
+            config.bash_chars_threshold,
+        ),
+        (
+            "assistant_turns",
+            analysis.assistant_turns,
+            config.assistant_turn_threshold,
+        ),
+        (
+            "research",
+            analysis.research_calls,
+            config.research_threshold,
+        ),
+    ];
+    candidates
+        .into_iter()
+        .filter_map(|(id, value, threshold)| {
+            threshold.filter(|&t| value >= t).map(|threshold| Gate {
+                id,
+                value,
+                threshold,
+            })
+        })
+        .collect()
+}
+
+pub fn stop_outcome(analysis: &Analysis, config: &Config) -> Outcome {
+    // No assistant reply at all: an aborted session, nothing to reflect on
+    if analysis.aborted() {
+        return Outcome::Light;
+    }
+
+    // Pure exploration never blocks, however many files it read, unless
+    // it was research enough to count on its own
+    let gates = triggered_gates(analysis, config);
+    let research = gates.iter().any(|gate| gate.id == "research");
+    if config.allow_read_only_sessions && analysis.mutating_calls == 0 && !research {
+        return Outcome::Light;
+    }
+
+    // Mostly searching, with an incidental edit: still exploration
+    if config
+        .max_search_ratio
+        .is_some_and(|ratio| analysis.search_ratio() > ratio)
+    {
+        return Outcome::Light;
+    }
+
+    // Breadth: many calls to a single tool don't make a session substantial
+    if config
+        .distinct_tools_threshold
+        .is_some_and(|threshold| analysis.distinct_tools() < threshold)
+    {
+        return Outcome::Light;
+    }
+
+    // Enough user messages, plus at least one activity gate. In score mode
+    // messages and tool turns trade off against each other instead, while
+    // the other gates still need `user_msg_threshold` messages.
+    let enough_messages = analysis.user_messages >= config.user_msg_threshold;
+    let substantial = match config.score_threshold {
+        Some(threshold) => {
+            analysis.score(config) >= threshold
+                || (enough_messages && gates.iter().any(|gate| gate.id != "tool_turns"))
+        }
+        None => enough_messages && !gates.is_empty(),
+    };
+    if !substantial {
+        return Outcome::Light;
+    }
+
+    // The diff is the record of a code-only session
+    if config.skip_if_code_only && analysis.code_only() && analysis.edit_heavy(config) {
+        return Outcome::Light;
+    }
+
+    if analysis.captured(config) {
+        Outcome::Captured
+    } else {
+        Outcome::Uncaptured
+    }
+}
+
+/// `stop_outcome` for an `always_substantial_globs` project: any session
+/// with a user message and a mutating call needs capture.
+fn strict_outcome(analysis: &Analysis, config: &Config) -> Outcome {
+    if analysis.aborted() || analysis.user_messages == 0 || analysis.mutating_calls == 0 {
+        Outcome::Light
+    } else if analysis.captured(config) {
+        Outcome::Captured
+    } else {
+        Outcome::Uncaptured
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::TEMPLATE_VARS;
+    use crate::clock::FixedClock;
+    use crate::config::USER_MSG_THRESHOLD;
+    use crate::pattern::PATTERN_PATH;
+    use crate::test_support::*;
+
+    #[test]
+    fn test_strict_flag_blocks_on_edit_only() {
+        let transcript =
+            substantial_transcript(&[memory_write_turn("Edit", "Memory/Learnings/x.md")]);
+        let (home, input) = stop_fixture("strict-edit", &transcript);
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+
+        assert_eq!(decide(&input, &Config::default(), &ctx), Decision::Allow);
+
+        let strict = Config {
+            require_write_not_edit: true,
+            ..Config::default()
+        };
+        assert!(matches!(
+            decide(&input, &strict, &ctx),
+            Decision::Block { .. }
+        ));
+    }
+
+    #[test]
+    fn test_rapid_identical_stops_block_once() {
+        let (home, input) = stop_fixture("dedupe", &substantial_transcript(&[]));
+        let env = home_env(&home);
+        let config = Config::default();
+
+        let first = decide(&input, &config, &context(&env, &FixedClock(1_000)));
+        assert!(matches!(first, Decision::Block { .. }));
+
+        let racing = decide(&input, &config, &context(&env, &FixedClock(1_002)));
+        assert_eq!(racing, Decision::Allow);
+
+        let later = decide(&input, &config, &context(&env, &FixedClock(1_000 + 3600)));
+        assert!(matches!(later, Decision::Block { .. }));
+    }
+
+    #[test]
+    fn test_capture_after_block_allows_next_stop() {
+        let transcript = substantial_transcript(&[]);
+        let (home, input) = stop_fixture("block-capture", &transcript);
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let config = Config::default();
+
+        assert!(matches!(
+            decide(&input, &config, &ctx),
+            Decision::Block { .. }
+        ));
+
+        // Claude answers the block by writing a learning; the next Stop
+        // arrives without `stop_hook_active` and must re-analyze.
+        let captured = format!(
+            "{}\n{}\n{}",
+            transcript,
+            r#"{"type":"human","message":{"content":"Stop hook feedback: reflect"}}"#,
+            memory_write_turn("Write", "Memory/Learnings/x.md")
+        );
+        fs::write(&input.transcript_path, captured).unwrap();
+        assert!(!input.stop_hook_active);
+        assert_eq!(decide(&input, &config, &ctx), Decision::Allow);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_ignore_stop_active_bypasses_loop_guard() {
+        let (home, mut input) = stop_fixture("ignore-stop-active", &substantial_transcript(&[]));
+        input.stop_hook_active = true;
+        let env = home_env(&home);
+        let config = Config::default();
+        assert_eq!(
+            decide(&input, &config, &context(&env, &SystemClock)),
+            Decision::Allow
+        );
+
+        let env = |key: &str| match key {
+            IGNORE_STOP_ACTIVE_ENV => Some("1".to_string()),
+            _ => env(key),
+        };
+        let decision = decide(&input, &config, &context(&env, &SystemClock));
+        assert!(matches!(decision, Decision::Block { .. }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_guard_resolves_symlinked_data_root() {
+        let home = TempDir::new("symlinked-home");
+        let real = TempDir::new("symlinked-real");
+        let cwd = real.path().join("project");
+        fs::create_dir_all(&cwd).unwrap();
+        std::os::unix::fs::symlink(real.path(), home.path().join("Data")).unwrap();
+        let transcript = cwd.join("transcript.jsonl");
+        fs::write(&transcript, substantial_transcript(&[])).unwrap();
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+
+        for cwd in [cwd.clone(), home.path().join("Data/project/../project")] {
+            let input = HookInput {
+                cwd: cwd.to_string_lossy().into_owned(),
+                transcript_path: transcript.to_string_lossy().into_owned(),
+                ..HookInput::default()
+            };
+            let config = Config {
+                dedupe_window_secs: 0,
+                ..Config::default()
+            };
+            assert!(
+                matches!(decide(&input, &config, &ctx), Decision::Block { .. }),
+                "{}",
+                input.cwd
+            );
+        }
+    }
+
+    #[test]
+    fn test_guard_rejects_sibling_prefix_directories() {
+        let home = TempDir::new("sibling-prefix");
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        fs::create_dir_all(home.path().join("Data")).unwrap();
+
+        for sibling in ["Database/x", "Data-old"] {
+            let cwd = home.path().join(sibling);
+            fs::create_dir_all(&cwd).unwrap();
+            let transcript = cwd.join("transcript.jsonl");
+            fs::write(&transcript, substantial_transcript(&[])).unwrap();
+            let input = HookInput {
+                cwd: cwd.to_string_lossy().into_owned(),
+                transcript_path: transcript.to_string_lossy().into_owned(),
+                ..HookInput::default()
+            };
+            assert_eq!(
+                decide(&input, &Config::default(), &ctx),
+                Decision::Allow,
+                "{}",
+                sibling
+            );
+        }
+    }
+
+    #[test]
+    fn test_sentinels_override_decision() {
+        let sentinel =
+            |marker: &str| format!(r#"{{"type":"human","message":{{"content":"{}"}}}}"#, marker);
+        let config = Config {
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+
+        let transcript = substantial_transcript(&[sentinel("[[session-reflect:allow]]")]);
+        let (home, input) = stop_fixture("sentinel-allow", &transcript);
+        let env = home_env(&home);
+        assert_eq!(
+            decide(&input, &config, &context(&env, &SystemClock)),
+            Decision::Allow
+        );
+
+        let (home, input) = stop_fixture("sentinel-block", &sentinel("[[session-reflect:block]]"));
+        let env = home_env(&home);
+        assert!(matches!(
+            decide(&input, &config, &context(&env, &SystemClock)),
+            Decision::Block { .. }
+        ));
+    }
+
+    #[test]
+    fn test_block_after_streak() {
+        let (home, first) = stop_fixture("streak", &substantial_transcript(&[]));
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let config = Config {
+            block_after_streak: 2,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+        let session = |name: &str, transcript: &str| {
+            let path = Path::new(&first.cwd).join(name);
+            fs::write(&path, transcript).unwrap();
+            HookInput {
+                cwd: first.cwd.clone(),
+                transcript_path: path.to_string_lossy().into_owned(),
+                ..HookInput::default()
+            }
+        };
+        let second = session("second.jsonl", &substantial_transcript(&[]));
+        let captured = session(
+            "captured.jsonl",
+            &substantial_transcript(&[memory_write_turn("Write", "Memory/Learnings/x.md")]),
+        );
+        let fourth = session("fourth.jsonl", &substantial_transcript(&[]));
+
+        assert_eq!(decide(&first, &config, &ctx), Decision::Allow);
+        assert_eq!(decide(&first, &config, &ctx), Decision::Allow);
+        assert!(matches!(
+            decide(&second, &config, &ctx),
+            Decision::Block {
+                code: ReasonCode::StreakThreshold,
+                ..
+            }
+        ));
+        assert_eq!(decide(&captured, &config, &ctx), Decision::Allow);
+        assert_eq!(decide(&fourth, &config, &ctx), Decision::Allow);
+    }
+
+    #[test]
+    fn test_block_after_streak_spans_project_subdirectories() {
+        let (home, input) = stop_fixture("streak-subdirs", &substantial_transcript(&[]));
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let config = Config {
+            block_after_streak: 2,
+            dedupe_window_secs: 0,
+            memory_root: Some(PathBuf::from(&input.cwd)),
+            ..Config::default()
+        };
+        let session = |subdir: &str| {
+            let cwd = Path::new(&input.cwd).join(subdir);
+            fs::create_dir_all(&cwd).unwrap();
+            let path = cwd.join("transcript.jsonl");
+            fs::write(&path, substantial_transcript(&[])).unwrap();
+            HookInput {
+                cwd: cwd.to_string_lossy().into_owned(),
+                transcript_path: path.to_string_lossy().into_owned(),
+                ..HookInput::default()
+            }
+        };
+
+        assert_eq!(decide(&session("src"), &config, &ctx), Decision::Allow);
+        for subdir in ["docs", "tests", "bench"] {
+            assert!(matches!(
+                decide(&session(subdir), &config, &ctx),
+                Decision::Block { .. }
+            ));
+        }
+
+        let dir = state::state_dir(ctx.env).unwrap();
+        let streaks = state::update(&dir, |state| state.streaks.clone()).unwrap();
+        assert_eq!(streaks.len(), 1);
+        assert_eq!(streaks[&input.cwd].len(), 2);
+    }
+
+    #[test]
+    fn test_always_inject_on_stop() {
+        let (home, input) = stop_fixture("always-inject", &tool_turn("Bash"));
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+
+        assert_eq!(decide(&input, &Config::default(), &ctx), Decision::Allow);
+
+        let config = Config {
+            always_inject_on_stop: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            decide(&input, &config, &ctx),
+            Decision::Inject {
+                event: HookEvent::Stop,
+                context: FALLBACK_REASON.to_string(),
+                code: ReasonCode::AlwaysInject,
+            }
+        );
+    }
+
+    #[test]
+    fn test_state_dir_env_overrides_home() {
+        let (home, input) = stop_fixture("state-dir", &substantial_transcript(&[]));
+        let state = home.path().join("sandbox/state");
+        let env = |key: &str| match key {
+            "HOME" => Some(home.path().to_string_lossy().into_owned()),
+            state::STATE_DIR_ENV => Some(state.to_string_lossy().into_owned()),
+            _ => None,
+        };
+
+        let decision = decide(&input, &Config::default(), &context(&env, &SystemClock));
+        assert!(matches!(decision, Decision::Block { .. }));
+        assert!(state.join(state::STATE_FILE).exists());
+        assert!(!home.path().join(".local/state").exists());
+    }
+
+    #[test]
+    fn test_unwritable_state_dir_still_decides() {
+        let (home, input) = stop_fixture("state-unwritable", &substantial_transcript(&[]));
+        // A file where the directory should be; unlike permissions, this
+        // also defeats root
+        let state = home.path().join("state");
+        fs::write(&state, "").unwrap();
+        let env = |key: &str| match key {
+            "HOME" => Some(home.path().to_string_lossy().into_owned()),
+            state::STATE_DIR_ENV => Some(state.to_string_lossy().into_owned()),
+            _ => None,
+        };
+        let ctx = context(&env, &SystemClock);
+        let config = Config {
+            precompact_cooldown_secs: 60,
+            ..Config::default()
+        };
+
+        // Neither dedupe nor the cooldown can remember the first call
+        for _ in 0..2 {
+            assert!(matches!(
+                decide(&input, &config, &ctx),
+                Decision::Block { .. }
+            ));
+        }
+        let compact = HookInput {
+            cwd: input.cwd.clone(),
+            trigger: Some("auto".to_string()),
+            ..HookInput::default()
+        };
+        for _ in 0..2 {
+            assert!(matches!(
+                decide(&compact, &config, &ctx),
+                Decision::Inject { context, .. } if context.starts_with(PRECOMPACT_PREFIX)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_active_hours_gate_blocks() {
+        let (home, input) = stop_fixture("active-hours", &substantial_transcript(&[]));
+        let env = home_env(&home);
+        let config = |outside_hours| Config {
+            active_hours: Some("09:00-18:00".to_string()),
+            outside_hours,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+        let at = |hour: u64, outside_hours| {
+            let clock = FixedClock(3 * 86_400 + hour * 3600);
+            decide(&input, &config(outside_hours), &context(&env, &clock))
+        };
+        let blocks = |d: Decision| matches!(d, Decision::Block { .. });
+
+        assert!(blocks(at(12, OutsideHours::Allow)));
+        assert!(blocks(at(23, OutsideHours::Block)));
+        assert_eq!(at(23, OutsideHours::Allow), Decision::Allow);
+        assert_eq!(
+            at(8, OutsideHours::Inject),
+            Decision::Inject {
+                event: HookEvent::Stop,
+                context: FALLBACK_REASON.to_string(),
+                code: ReasonCode::OutsideActiveHours,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_active_hours() {
+        assert_eq!(parse_active_hours("09:00-18:30"), Some((32_400, 66_600)));
+        assert_eq!(parse_active_hours("22:00 - 06:00"), Some((79_200, 21_600)));
+        assert_eq!(parse_active_hours("9-18"), None);
+        assert_eq!(parse_active_hours("24:00-01:00"), None);
+    }
+
+    #[test]
+    fn test_empty_transcripts_allow() {
+        for (name, transcript) in [("empty", ""), ("blank", "\n   \n\t\n")] {
+            let (home, input) = stop_fixture(&format!("transcript-{}", name), transcript);
+            let env = home_env(&home);
+            let config = Config {
+                user_msg_threshold: 0,
+                tool_turn_threshold: 0,
+                allow_read_only_sessions: false,
+                ..Config::default()
+            };
+            assert_eq!(
+                decide(&input, &config, &context(&env, &SystemClock)),
+                Decision::Allow
+            );
+        }
+    }
+
+    #[test]
+    fn test_session_start_message_by_source() {
+        let (home, input) = stop_fixture("session-start", "");
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let start = |source: &str, config: &Config| {
+            let input = HookInput {
+                hook_event_name: Some("SessionStart".to_string()),
+                source: Some(source.to_string()),
+                ..input.clone()
+            };
+            decide(&input, config, &ctx)
+        };
+        let inject = |context: &str| Decision::Inject {
+            event: HookEvent::SessionStart,
+            context: context.to_string(),
+            code: ReasonCode::SessionStart,
+        };
+
+        let defaults = Config::default();
+        assert_eq!(start("resume", &defaults), inject(SESSION_START_RESUME));
+        for source in ["startup", "clear", "compact"] {
+            assert_eq!(start(source, &defaults), Decision::Allow, "{}", source);
+        }
+
+        let config = Config {
+            session_start_messages: [
+                ("startup".to_string(), "Capture as you go.".to_string()),
+                ("resume".to_string(), String::new()),
+            ]
+            .into(),
+            ..Config::default()
+        };
+        assert_eq!(start("startup", &config), inject("Capture as you go."));
+        assert_eq!(start("resume", &config), Decision::Allow);
+        assert_eq!(start("clear", &config), Decision::Allow);
+    }
+
+    #[test]
+    fn test_skip_if_code_only_spares_source_edits() {
+        let session = |paths: &[&str]| {
+            let edits: Vec<String> = paths.iter().map(|p| memory_write_turn("Edit", p)).collect();
+            analyze(&substantial_transcript(&edits))
+        };
+        let config = Config {
+            skip_if_code_only: true,
+            ..Config::default()
+        };
+
+        let code = session(&["src/main.rs", "src/lib.rs"]);
+        assert_eq!(stop_outcome(&code, &Config::default()), Outcome::Uncaptured);
+        assert_eq!(stop_outcome(&code, &config), Outcome::Light);
+        let prose = session(&["src/main.rs", "docs/design.md"]);
+        assert_eq!(stop_outcome(&prose, &config), Outcome::Uncaptured);
+        // Shell-only work edited no code
+        assert_eq!(stop_outcome(&session(&[]), &config), Outcome::Uncaptured);
+
+        let mut reads: Vec<String> = (0..24).map(|_| tool_turn("Read")).collect();
+        reads.push(memory_write_turn("Edit", "src/main.rs"));
+        let browsing = analyze(&substantial_transcript(&reads));
+        let config = Config {
+            min_edit_intensity: Some(1.0),
+            ..config
+        };
+        assert_eq!(stop_outcome(&code, &config), Outcome::Light);
+        assert_eq!(stop_outcome(&browsing, &config), Outcome::Uncaptured);
+    }
+
+    #[test]
+    fn test_aborted_session_allows() {
+        let transcript = [r#"{"type":"human"}"#; 8].join("\n");
+        let (home, input) = stop_fixture("aborted", &transcript);
+        let env = home_env(&home);
+        let config = Config {
+            user_msg_threshold: 1,
+            tool_turn_threshold: 0,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            decide(&input, &config, &context(&env, &SystemClock)),
+            Decision::Allow
+        );
+    }
+
+    /// `<home>/claude/projects/<encoded cwd>`, created, for use with
+    /// `CLAUDE_CONFIG_DIR=<home>/claude`.
+    fn claude_project_dir(home: &TempDir, cwd: &str) -> PathBuf {
+        let encoded: String = cwd
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let dir = home.path().join("claude/projects").join(encoded);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn claude_env(home: &TempDir) -> impl Fn(&str) -> Option<String> + '_ {
+        move |key| match key {
+            "HOME" => Some(home.path().to_string_lossy().into_owned()),
+            "CLAUDE_CONFIG_DIR" => Some(home.path().join("claude").to_string_lossy().into_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_discover_transcript_uses_newest_in_projects_dir() {
+        let (home, input) = stop_fixture("discover-transcript", "");
+        let projects = claude_project_dir(&home, &input.cwd);
+        fs::write(projects.join("older.jsonl"), r#"{"type":"human"}"#).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        fs::write(projects.join("newer.jsonl"), substantial_transcript(&[])).unwrap();
+        fs::write(projects.join("notes.txt"), "").unwrap();
+
+        let env = claude_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let missing = HookInput {
+            transcript_path: String::new(),
+            ..input
+        };
+        let config = Config {
+            discover_transcript: true,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+
+        assert!(matches!(
+            decide(&missing, &config, &ctx),
+            Decision::Block { .. }
+        ));
+        assert_eq!(decide(&missing, &Config::default(), &ctx), Decision::Allow);
+    }
+
+    #[test]
+    fn test_directory_transcript_path_uses_newest_jsonl_inside() {
+        let (home, input) = stop_fixture("transcript-dir", "");
+        let dir = Path::new(&input.cwd).join("transcripts");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("older.jsonl"), r#"{"type":"human"}"#).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        fs::write(dir.join("newer.jsonl"), substantial_transcript(&[])).unwrap();
+
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let input = HookInput {
+            transcript_path: dir.to_string_lossy().into_owned(),
+            ..input
+        };
+        let config = Config {
+            discover_transcript: true,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+
+        assert!(matches!(
+            decide(&input, &config, &ctx),
+            Decision::Block { .. }
+        ));
+        assert_eq!(decide(&input, &Config::default(), &ctx), Decision::Allow);
+    }
+
+    #[test]
+    fn test_notify_command_runs_only_on_block() {
+        let (home, input) = stop_fixture("notify", &substantial_transcript(&[]));
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let marker = home.path().join("notified");
+        let config = Config {
+            notify_command: Some(format!(
+                "printf %s \"$SESSION_REFLECT_REASON\" > '{}'",
+                marker.display()
+            )),
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+
+        let light = HookInput {
+            transcript_path: String::new(),
+            ..input.clone()
+        };
+        assert_eq!(decide(&light, &config, &ctx), Decision::Allow);
+        assert!(!marker.exists());
+
+        assert!(matches!(
+            decide(&input, &config, &ctx),
+            Decision::Block { .. }
+        ));
+        assert_eq!(fs::read_to_string(&marker).unwrap(), FALLBACK_REASON);
+
+        // A failing or hanging command changes nothing
+        for command in ["exit 3", "sleep 10"] {
+            let config = Config {
+                notify_command: Some(command.to_string()),
+                ..config.clone()
+            };
+            assert!(matches!(
+                decide(&input, &config, &ctx),
+                Decision::Block { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_lookback_sessions_credit_prior_capture() {
+        let (home, input) = stop_fixture("lookback", "");
+        let projects = claude_project_dir(&home, &input.cwd);
+        let write = |name: &str, transcript: String| {
+            fs::write(projects.join(name), transcript).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        };
+        write(
+            "captured.jsonl",
+            memory_write_turn("Write", "Memory/Learnings/x.md"),
+        );
+        write("between.jsonl", substantial_transcript(&[]));
+        write("current.jsonl", substantial_transcript(&[]));
+        let current = HookInput {
+            transcript_path: projects
+                .join("current.jsonl")
+                .to_string_lossy()
+                .into_owned(),
+            ..input
+        };
+        let env = claude_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let lookback = |n| Config {
+            lookback_sessions: n,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+
+        assert!(matches!(
+            decide(&current, &lookback(0), &ctx),
+            Decision::Block { .. }
+        ));
+        assert!(matches!(
+            decide(&current, &lookback(1), &ctx),
+            Decision::Block { .. }
+        ));
+        assert_eq!(decide(&current, &lookback(2), &ctx), Decision::Allow);
+    }
+
+    #[test]
+    fn test_warmup_sessions_allow_then_enforce() {
+        let (home, input) = stop_fixture("warmup", "");
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let config = Config {
+            warmup_sessions: 2,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+        let session = |name: &str| {
+            let path = Path::new(&input.cwd).join(name);
+            fs::write(&path, substantial_transcript(&[])).unwrap();
+            HookInput {
+                cwd: input.cwd.clone(),
+                transcript_path: path.to_string_lossy().into_owned(),
+                ..HookInput::default()
+            }
+        };
+
+        for name in ["one.jsonl", "two.jsonl", "one.jsonl"] {
+            assert_eq!(
+                decide(&session(name), &config, &ctx),
+                Decision::Allow,
+                "{}",
+                name
+            );
+        }
+        assert!(matches!(
+            decide(&session("three.jsonl"), &config, &ctx),
+            Decision::Block { .. }
+        ));
+    }
+
+    #[test]
+    fn test_code_heavy_session_selects_code_pattern() {
+        let mut lines = vec![r#"{"type":"human"}"#.to_string(); 4];
+        lines.extend((0..10).map(|i| memory_write_turn("Edit", &format!("src/{}.rs", i))));
+        lines.push(memory_write_turn("Write", "README.md"));
+        lines.push(tool_turn("Bash"));
+        let (home, input) = stop_fixture("work-type", &lines.join("\n"));
+        let patterns = Path::new(&input.cwd).join("Patterns");
+        fs::create_dir_all(&patterns).unwrap();
+        fs::write(
+            patterns.join("Code.md"),
+            "Note the design behind this change.",
+        )
+        .unwrap();
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+
+        let reason = |label: &str| {
+            let config = Config {
+                patterns: [(label.to_string(), "Patterns/Code.md".to_string())].into(),
+                dedupe_window_secs: 0,
+                ..Config::default()
+            };
+            match decide(&input, &config, &ctx) {
+                Decision::Block { reason, .. } => reason,
+                other => panic!("expected block, got {:?}", other),
+            }
+        };
+
+        assert_eq!(reason("code"), "Note the design behind this change.");
+        assert_eq!(reason("docs"), FALLBACK_REASON);
+    }
+
+    #[test]
+    fn test_extra_patterns_joined_with_separator() {
+        let (home, input) = stop_fixture("extra-patterns", &substantial_transcript(&[]));
+        let pattern = Path::new(&input.cwd).join(PATTERN_PATH);
+        fs::create_dir_all(pattern.parent().unwrap()).unwrap();
+        fs::write(&pattern, "# Session Reflect\n\nCapture learnings.\n").unwrap();
+        fs::write(
+            Path::new(&input.cwd).join("Team.md"),
+            "---\nx: 1\n---\nTag the team.\n",
+        )
+        .unwrap();
+        let env = home_env(&home);
+        let config = Config {
+            extra_patterns: vec!["Team.md".to_string(), "Missing.md".to_string()],
+            pattern_separator: r"\n\n---\n\n".to_string(),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            decide(&input, &config, &context(&env, &SystemClock)),
+            Decision::Block {
+                reason: "Capture learnings.\n\n---\n\nTag the team.".to_string(),
+                code: ReasonCode::SubstantialNoMemory,
+            }
+        );
+    }
+
+    #[test]
+    fn test_block_reason_reports_threshold_ratio() {
+        let extra: Vec<String> = (0..13).map(|_| tool_turn("Bash")).collect();
+        let (home, input) = stop_fixture("threshold-ratio", &substantial_transcript(&extra));
+        let pattern = Path::new(&input.cwd).join(PATTERN_PATH);
+        fs::create_dir_all(pattern.parent().unwrap()).unwrap();
+        fs::write(
+            &pattern,
+            "# Session Reflect\n\nAt {{threshold_ratio}} of the threshold ({{tool_turns}} tool turns, {{user_messages}} messages).\n",
+        )
+        .unwrap();
+        let env = home_env(&home);
+
+        assert_eq!(
+            decide(&input, &Config::default(), &context(&env, &SystemClock)),
+            Decision::Block {
+                reason: "At 230% of the threshold (23 tool turns, 4 messages).".to_string(),
+                code: ReasonCode::SubstantialNoMemory,
+            }
+        );
+    }
+
+    #[test]
+    fn test_verbose_reason_lists_every_tripped_gate() {
+        let error =
+            r#"{"type":"user","message":{"content":[{"type":"tool_result","is_error":true}]}}"#;
+        let (home, input) = stop_fixture("gates", &substantial_transcript(&[error.to_string()]));
+        let env = home_env(&home);
+        let config = Config {
+            error_threshold: Some(1),
+            verbose_reasons: true,
+            ..Config::default()
+        };
+
+        let reason = match decide(&input, &config, &context(&env, &SystemClock)) {
+            Decision::Block { reason, .. } => reason,
+            other => panic!("{:?}", other),
+        };
+        assert!(reason.starts_with(FALLBACK_REASON));
+        assert!(reason.contains("\n- tool_turns: 10 (threshold 10)"));
+        assert!(reason.contains("\n- tool_errors: 1 (threshold 1)"));
+        assert!(!reason.contains("test_runs"));
+    }
+
+    #[test]
+    fn test_include_pattern_path_links_loaded_pattern() {
+        let (home, input) = stop_fixture("pattern-path", &substantial_transcript(&[]));
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let config = Config {
+            include_pattern_path: true,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+        let reason = |config: &Config| match decide(&input, config, &ctx) {
+            Decision::Block { reason, .. } => reason,
+            other => panic!("{:?}", other),
+        };
+
+        assert_eq!(reason(&config), FALLBACK_REASON);
+
+        let pattern = Path::new(&input.cwd).join(PATTERN_PATH);
+        fs::create_dir_all(pattern.parent().unwrap()).unwrap();
+        fs::write(&pattern, "# Session Reflect\n\nCapture learnings.\n").unwrap();
+        let link = fs::canonicalize(&pattern).unwrap();
+        assert_eq!(
+            reason(&config),
+            format!("Capture learnings.\n\nSee: {}", link.display())
+        );
+        assert_eq!(
+            reason(&Config {
+                include_pattern_path: false,
+                ..config
+            }),
+            "Capture learnings."
+        );
+    }
+
+    #[test]
+    fn test_max_reason_chars_truncates_on_char_boundary() {
+        let (home, input) = stop_fixture("max-reason", &substantial_transcript(&[]));
+        let pattern = Path::new(&input.cwd).join(PATTERN_PATH);
+        fs::create_dir_all(pattern.parent().unwrap()).unwrap();
+        fs::write(&pattern, "Réfléchis à la séance, puis capture.\n").unwrap();
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let reason = |max| {
+            let config = Config {
+                max_reason_chars: max,
+                dedupe_window_secs: 0,
+                ..Config::default()
+            };
+            match decide(&input, &config, &ctx) {
+                Decision::Block { reason, .. } => reason,
+                other => panic!("{:?}", other),
+            }
+        };
+
+        assert_eq!(reason(None), "Réfléchis à la séance, puis capture.");
+        assert_eq!(reason(Some(8)), "Réfléch…");
+        assert_eq!(reason(Some(8)).chars().count(), 8);
+        assert_eq!(reason(Some(36)), "Réfléchis à la séance, puis capture.");
+        assert_eq!(reason(Some(2)), "R…");
+        assert_eq!(reason(Some(1)), "R");
+        assert_eq!(reason(Some(0)), "");
+    }
+
+    #[test]
+    fn test_interpolate_env_fills_prompt_from_environment() {
+        let (home, input) = stop_fixture("interpolate-env", &substantial_transcript(&[]));
+        let pattern = Path::new(&input.cwd).join(PATTERN_PATH);
+        fs::create_dir_all(pattern.parent().unwrap()).unwrap();
+        fs::write(&pattern, "Capture what ${CLAUDE_PROJECT_DIR} taught you.\n").unwrap();
+        let home_env = home_env(&home);
+        let env = |key: &str| match key {
+            "CLAUDE_PROJECT_DIR" => Some("acme-api".to_string()),
+            _ => home_env(key),
+        };
+        let config = Config {
+            interpolate_env: InterpolateEnv::Keep,
+            ..Config::default()
+        };
+
+        match decide(&input, &config, &context(&env, &SystemClock)) {
+            Decision::Block { reason, .. } => {
+                assert_eq!(reason, "Capture what acme-api taught you.")
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_include_git_log_appends_recent_commits() {
+        let (home, input) = stop_fixture("git-log", &substantial_transcript(&[]));
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let config = Config {
+            include_git_log: true,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+        let reason = || match decide(&input, &config, &ctx) {
+            Decision::Block { reason, .. } => reason,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(reason(), FALLBACK_REASON, "not a repo yet");
+
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args([
+                    "-C",
+                    &input.cwd,
+                    "-c",
+                    "user.name=t",
+                    "-c",
+                    "user.email=t@example.com",
+                ])
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {:?}", args);
+        };
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "Add the parser"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "Fix the lexer"]);
+
+        let reason = reason();
+        let (prompt, log) = reason.split_once("\n\nRecent commits:\n").unwrap();
+        assert_eq!(prompt, FALLBACK_REASON);
+        let subjects: Vec<_> = log.lines().map(|l| l.split_once(' ').unwrap().1).collect();
+        assert_eq!(subjects, ["Fix the lexer", "Add the parser"]);
+    }
+
+    #[test]
+    fn test_decline_phrase_allows_stop() {
+        let decline = r#"{"type":"user","message":{"content":"Thanks! No need to capture anything this time."}}"#;
+        let (home, input) =
+            stop_fixture("declined", &substantial_transcript(&[decline.to_string()]));
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+
+        assert_eq!(decide(&input, &Config::default(), &ctx), Decision::Allow);
+
+        let config = Config {
+            decline_phrases: Vec::new(),
+            ..Config::default()
+        };
+        assert!(matches!(
+            decide(&input, &config, &ctx),
+            Decision::Block { .. }
+        ));
+    }
+
+    #[test]
+    fn test_read_only_session_allows() {
+        let mut lines = vec![r#"{"type":"human"}"#.to_string(); USER_MSG_THRESHOLD];
+        lines.extend((0..20).map(|_| tool_turn("Read")));
+        let (home, input) = stop_fixture("read-only", &lines.join("\n"));
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+
+        assert_eq!(decide(&input, &Config::default(), &ctx), Decision::Allow);
+
+        let config = Config {
+            allow_read_only_sessions: false,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+        assert!(matches!(
+            decide(&input, &config, &ctx),
+            Decision::Block { .. }
+        ));
+    }
+
+    #[test]
+    fn test_max_search_ratio_allows_search_sessions() {
+        let mut extra = vec![tool_turn("Edit")];
+        extra.extend((0..30).map(|i| tool_turn(["Grep", "Glob"][i % 2])));
+        let searching = analyze(&substantial_transcript(&extra));
+        assert_eq!((searching.search_calls, searching.tool_calls), (30, 41));
+        let extra = ["Edit", "Grep"].map(tool_turn);
+        let balanced = analyze(&substantial_transcript(&extra));
+
+        let config = Config {
+            max_search_ratio: Some(0.5),
+            ..Config::default()
+        };
+        assert_eq!(
+            stop_outcome(&searching, &Config::default()),
+            Outcome::Uncaptured
+        );
+        assert_eq!(stop_outcome(&searching, &config), Outcome::Light);
+        assert_eq!(stop_outcome(&balanced, &config), Outcome::Uncaptured);
+    }
+
+    #[test]
+    fn test_distinct_tools_threshold() {
+        let extra = ["Edit", "Grep"].map(tool_turn);
+        let analysis = analyze(&substantial_transcript(&extra));
+        assert_eq!(analysis.distinct_tools(), 3);
+
+        let config = |threshold| Config {
+            distinct_tools_threshold: threshold,
+            ..Config::default()
+        };
+        assert_eq!(stop_outcome(&analysis, &config(None)), Outcome::Uncaptured);
+        assert_eq!(
+            stop_outcome(&analysis, &config(Some(3))),
+            Outcome::Uncaptured
+        );
+        assert_eq!(stop_outcome(&analysis, &config(Some(4))), Outcome::Light);
+    }
+
+    #[test]
+    fn test_test_run_threshold_marks_substantial() {
+        let analysis = Analysis {
+            user_messages: USER_MSG_THRESHOLD,
+            assistant_turns: 3,
+            tool_using_turns: 3,
+            non_task_turns: 3,
+            test_runs: 3,
+            mutating_calls: 3,
+            ..Analysis::default()
+        };
+        assert_eq!(stop_outcome(&analysis, &Config::default()), Outcome::Light);
+
+        let config = Config {
+            test_run_threshold: Some(3),
+            ..Config::default()
+        };
+        assert_eq!(stop_outcome(&analysis, &config), Outcome::Uncaptured);
+
+        let config = Config {
+            test_run_threshold: Some(4),
+            ..config
+        };
+        assert_eq!(stop_outcome(&analysis, &config), Outcome::Light);
+    }
+
+    #[test]
+    fn test_assistant_turn_threshold_marks_substantial() {
+        let reply = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Consider the trade-off."}]}}"#;
+        let mut lines = vec![r#"{"type":"human"}"#.to_string(); USER_MSG_THRESHOLD];
+        lines.extend((0..12).map(|_| reply.to_string()));
+        lines.push(tool_turn("Edit"));
+        let analysis = analyze(&lines.join("\n"));
+        assert_eq!(
+            (analysis.assistant_turns, analysis.tool_using_turns),
+            (13, 1)
+        );
+        assert_eq!(stop_outcome(&analysis, &Config::default()), Outcome::Light);
+
+        let config = Config {
+            assistant_turn_threshold: Some(13),
+            ..Config::default()
+        };
+        assert_eq!(stop_outcome(&analysis, &config), Outcome::Uncaptured);
+        let config = Config {
+            assistant_turn_threshold: Some(14),
+            ..config
+        };
+        assert_eq!(stop_outcome(&analysis, &config), Outcome::Light);
+    }
+
+    #[test]
+    fn test_max_capture_gap_spans_sessions() {
+        let captured =
+            substantial_transcript(&[memory_write_turn("Write", "Memory/Learnings/x.md")]);
+        let (home, first) = stop_fixture("capture-gap", &captured);
+        let env = home_env(&home);
+        let config = Config {
+            max_capture_gap_hours: Some(3),
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+        let later = Path::new(&first.cwd).join("later.jsonl");
+        fs::write(&later, substantial_transcript(&[])).unwrap();
+        let later = HookInput {
+            transcript_path: later.to_string_lossy().into_owned(),
+            ..first.clone()
+        };
+        let at = |secs: u64| FixedClock(1_000_000 + secs);
+
+        assert!(matches!(
+            decide(&later, &config, &context(&env, &at(0))),
+            Decision::Block { .. }
+        ));
+        assert_eq!(
+            decide(&first, &config, &context(&env, &at(0))),
+            Decision::Allow
+        );
+        assert_eq!(
+            decide(&later, &config, &context(&env, &at(3 * 3600))),
+            Decision::Allow
+        );
+        assert!(matches!(
+            decide(&later, &config, &context(&env, &at(3 * 3600 + 1))),
+            Decision::Block { .. }
+        ));
+    }
+
+    #[test]
+    fn test_log_syslog_sends_decision_and_counts() {
+        let (home, input) = stop_fixture("syslog", &substantial_transcript(&[]));
+        let env = home_env(&home);
+        let log = RecordingLog::default();
+        let ctx = Context {
+            syslog: &log,
+            ..context(&env, &SystemClock)
+        };
+        let config = Config {
+            log_syslog: true,
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+
+        decide(&input, &Config::default(), &ctx);
+        assert!(log.0.borrow().is_empty());
+        decide(&input, &config, &ctx);
+        assert_eq!(
+            log.0.borrow().as_slice(),
+            [format!(
+                "event=Stop decision=block code=SUBSTANTIAL_NO_MEMORY user_messages={} tool_turns=10 memory_written=false cwd={:?}",
+                USER_MSG_THRESHOLD,
+                input.cwd
+            )]
+        );
+
+        // A discovered transcript's counts, not the payload path's
+        let dir = Path::new(&input.cwd).join("transcripts");
+        fs::create_dir_all(&dir).unwrap();
+        fs::rename(&input.transcript_path, dir.join("session.jsonl")).unwrap();
+        let input = HookInput {
+            transcript_path: dir.to_string_lossy().into_owned(),
+            ..input
+        };
+        let config = Config {
+            discover_transcript: true,
+            ..config
+        };
+        log.0.borrow_mut().clear();
+        decide(&input, &config, &ctx);
+        let line = log.0.borrow()[0].clone();
+        assert!(
+            line.contains(" tool_turns=10 memory_written=false "),
+            "{}",
+            line
+        );
+    }
+
+    #[test]
+    fn test_capture_files_count_as_memory_writes() {
+        let (home, input) = stop_fixture("capture-files", "");
+        let log = Path::new(&input.cwd).join("DECISIONS.md");
+        let cwd_line = serde_json::json!({"type": "user", "cwd": input.cwd}).to_string();
+        let write = memory_write_turn("Edit", &log.to_string_lossy());
+        fs::write(
+            &input.transcript_path,
+            substantial_transcript(&[cwd_line, write]),
+        )
+        .unwrap();
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let config = Config {
+            capture_files: vec!["DECISIONS.md".to_string()],
+            dedupe_window_secs: 0,
+            ..Config::default()
+        };
+
+        assert_eq!(decide(&input, &config, &ctx), Decision::Allow);
+        let other = Config {
+            capture_files: vec!["docs/DECISIONS.md".to_string()],
+            ..config
+        };
+        assert!(matches!(
+            decide(&input, &other, &ctx),
+            Decision::Block { .. }
+        ));
+    }
+
+    #[test]
+    fn test_always_substantial_globs_block_tiny_sessions() {
+        let tiny = [r#"{"type":"human"}"#.to_string(), tool_turn("Edit")].join("\n");
+        let (home, input) = stop_fixture("always-substantial", &tiny);
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+        let project = canonical(&input.cwd);
+        let glob = format!("{}/**", Path::new(&project).parent().unwrap().display());
+        let flagged = Config {
+            always_substantial_globs: vec![glob],
+            ..Config::default()
+        };
+        let elsewhere = Config {
+            always_substantial_globs: vec!["/elsewhere/**".to_string()],
+            ..Config::default()
+        };
+
+        assert!(matches!(
+            decide(&input, &flagged, &ctx),
+            Decision::Block { .. }
+        ));
+        assert_eq!(decide(&input, &elsewhere, &ctx), Decision::Allow);
+        assert_eq!(decide(&input, &Config::default(), &ctx), Decision::Allow);
+    }
+
+    #[test]
+    fn test_research_threshold_marks_substantial() {
+        let mut lines = vec![r#"{"type":"human"}"#.to_string(); USER_MSG_THRESHOLD];
+        lines.extend((0..4).map(|_| tool_turn("WebFetch")));
+        let analysis = analyze(&lines.join("\n"));
+        assert_eq!((analysis.research_calls, analysis.mutating_calls), (4, 0));
+        assert_eq!(stop_outcome(&analysis, &Config::default()), Outcome::Light);
+
+        let config = Config {
+            research_threshold: Some(4),
+            ..Config::default()
+        };
+        assert_eq!(stop_outcome(&analysis, &config), Outcome::Uncaptured);
+        let config = Config {
+            research_threshold: Some(5),
+            ..config
+        };
+        assert_eq!(stop_outcome(&analysis, &config), Outcome::Light);
+    }
+
+    #[test]
+    fn test_score_mode_trades_messages_for_tool_turns() {
+        let analysis = Analysis {
+            user_messages: 1,
+            assistant_turns: 30,
+            tool_using_turns: 30,
+            non_task_turns: 30,
+            mutating_calls: 30,
+            ..Analysis::default()
+        };
+        assert_eq!(stop_outcome(&analysis, &Config::default()), Outcome::Light);
+
+        let config = Config {
+            score_threshold: Some(20.0),
+            user_weight: 2.0,
+            tool_weight: 0.5,
+            ..Config::default()
+        };
+        assert_eq!(analysis.score(&config), 17.0);
+        assert_eq!(stop_outcome(&analysis, &config), Outcome::Light);
+
+        let config = Config {
+            tool_weight: 1.0,
+            ..config
+        };
+        assert_eq!(stop_outcome(&analysis, &config), Outcome::Uncaptured);
+    }
+
+    #[test]
+    fn test_precompact_uses_transcript_stats() {
+        let (home, mut input) = stop_fixture("precompact-stats", &substantial_transcript(&[]));
+        let pattern = Path::new(&input.cwd).join(PATTERN_PATH);
+        fs::create_dir_all(pattern.parent().unwrap()).unwrap();
+        fs::write(
+            &pattern,
+            "# Session Reflect\n\n{{tool_turns}} tool turns so far.\n\n{{user_messages}} messages, {{task_calls}} delegations, {{test_runs}} test runs, {{tool_errors}} errors, {{distinct_tools}} tools; {{threshold_ratio}} of the way.\n",
+        )
+        .unwrap();
+        input.trigger = Some("auto".to_string());
+        let env = home_env(&home);
+        let ctx = context(&env, &SystemClock);
+
+        let context = |input: &HookInput| match decide(input, &Config::default(), &ctx) {
+            Decision::Inject { context, .. } => context,
+            other => panic!("{:?}", other),
+        };
+        let counted = context(&input);
+        assert!(
+            counted.starts_with(&format!("{}10 tool turns so far.", PRECOMPACT_PREFIX)),
+            "{}",
+            counted
+        );
+
+        // No transcript to count: stand-ins, never a raw placeholder
+        input.transcript_path.clear();
+        let uncounted = context(&input);
+        assert!(uncounted.contains("an unknown number of tool turns so far."));
+        for name in TEMPLATE_VARS {
+            assert!(
+                !uncounted.contains(&format!("{{{{{}}}}}", name)),
+                "{}",
+                uncounted
+            );
+        }
+    }
+
+    #[test]
+    fn test_precompact_cooldown_shortens_repeat_injections() {
+        let (home, mut input) = stop_fixture("precompact-cooldown", "");
+        input.transcript_path.clear();
+        input.trigger = Some("auto".to_string());
+        let env = home_env(&home);
+        let config = Config {
+            precompact_cooldown_secs: 60,
+            ..Config::default()
+        };
+        let context_at =
+            |input: &HookInput, now| match decide(input, &config, &context(&env, &FixedClock(now)))
+            {
+                Decision::Inject { context, .. } => context,
+                other => panic!("{:?}", other),
+            };
+        let full = format!("{}{}", PRECOMPACT_PREFIX, FALLBACK_REASON);
+
+        assert_eq!(context_at(&input, 1_000), full);
+        assert_eq!(context_at(&input, 1_030), PRECOMPACT_REMINDER);
+        assert_eq!(context_at(&input, 1_061), full);
+
+        input.trigger = Some("manual".to_string());
+        assert_eq!(context_at(&input, 1_062), full);
+    }
+
+    fn all_decisions() -> [Decision; 3] {
+        [
+            Decision::Allow,
+            Decision::Block {
+                reason: "r".to_string(),
+                code: ReasonCode::SubstantialNoMemory,
+            },
+            Decision::Inject {
+                event: HookEvent::PreCompact,
+                context: "c".to_string(),
+                code: ReasonCode::Precompact,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_exit_codes_default_to_success() {
+        for decision in all_decisions() {
+            assert_eq!(decision.exit_code(false), 0, "{:?}", decision);
+        }
+    }
+
+    #[test]
+    fn test_exit_codes_mapped_when_enabled() {
+        let [allow, block, inject] = all_decisions();
+        assert_eq!(allow.exit_code(true), 0);
+        assert_eq!(block.exit_code(true), 2);
+        assert_eq!(inject.exit_code(true), 0);
+
+        let env = |key: &str| (key == "SESSION_REFLECT_EXIT_CODES").then(|| "1".to_string());
+        assert!(Config::load("", &env).exit_codes);
+    }
+}
@@ -0,0 +1,44 @@
+use std::io::Read;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How long the hook waits for stdin to reach EOF by default.
+pub const STDIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Read `reader` to EOF on a helper thread, giving up after `timeout`. A hook
+/// run with an open terminal or an unclosed pipe would otherwise block
+/// forever. `None` on timeout or read error; on timeout the helper thread is
+/// abandoned, which is fine since the process exits right after.
+pub fn read_with_timeout<R: Read + Send + 'static>(
+    mut reader: R,
+    timeout: Duration,
+) -> Option<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = String::new();
+        let result = reader.read_to_string(&mut buf).map(|_| buf);
+        let _ = tx.send(result);
+    });
+    rx.recv_timeout(timeout).ok()?.ok()
+}
+
+/// `SESSION_REFLECT_STDIN_TIMEOUT_MS`, else [`STDIN_TIMEOUT`]. Env-only, since
+/// the config that depends on `cwd` can't be loaded before the payload is read.
+pub fn stdin_timeout(env: &dyn Fn(&str) -> Option<String>) -> Duration {
+    env("SESSION_REFLECT_STDIN_TIMEOUT_MS")
+        .and_then(|ms| ms.trim().parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(STDIN_TIMEOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_closed_input() {
+        let input = read_with_timeout(&b"{}"[..], STDIN_TIMEOUT);
+        assert_eq!(input.as_deref(), Some("{}"));
+    }
+}
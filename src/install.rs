@@ -0,0 +1,154 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Version of the running binary.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const BINARY_NAME: &str = "session-reflect";
+
+/// Result of checking each configured session-reflect hook.
+#[derive(Debug, Serialize)]
+pub struct InstallReport {
+    /// Version of the binary doing the check.
+    pub expected: String,
+    pub hooks: Vec<HookCheck>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HookCheck {
+    /// Hook event the command is registered for.
+    pub event: String,
+    pub command: String,
+    /// Executable the command resolves to, if any.
+    pub resolved: Option<String>,
+    /// What it reported for `--version`.
+    pub version: Option<String>,
+}
+
+impl HookCheck {
+    pub fn matches(&self, expected: &str) -> bool {
+        self.version.as_deref() == Some(expected)
+    }
+}
+
+impl InstallReport {
+    /// At least one hook is configured and every one reports `expected`.
+    pub fn ok(&self) -> bool {
+        !self.hooks.is_empty() && self.hooks.iter().all(|h| h.matches(&self.expected))
+    }
+}
+
+/// `~/.claude/settings.json`.
+pub fn default_settings_path(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    env("HOME").map(|home| Path::new(&home).join(".claude/settings.json"))
+}
+
+/// Find session-reflect hook commands in a Claude settings file, resolve
+/// each to an executable and ask it for `--version`. Read-only: nothing is
+/// run except the resolved binary's `--version`.
+pub fn verify_install(
+    settings: &Path,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> std::io::Result<InstallReport> {
+    let text = fs::read_to_string(settings)?;
+    let settings: Value = serde_json::from_str(&text)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    let hooks = hook_commands(&settings)
+        .into_iter()
+        .map(|(event, command)| {
+            let resolved = resolve_binary(&command, env);
+            let version = resolved.as_deref().and_then(binary_version);
+            HookCheck {
+                event,
+                command,
+                resolved: resolved.map(|p| p.to_string_lossy().into_owned()),
+                version,
+            }
+        })
+        .collect();
+
+    Ok(InstallReport {
+        expected: VERSION.to_string(),
+        hooks,
+    })
+}
+
+/// `(event, command)` for every hook command mentioning session-reflect.
+fn hook_commands(settings: &Value) -> Vec<(String, String)> {
+    let events = match settings.get("hooks").and_then(|h| h.as_object()) {
+        Some(e) => e,
+        None => return Vec::new(),
+    };
+    events
+        .iter()
+        .flat_map(|(event, matchers)| {
+            matchers
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|m| m.get("hooks").and_then(|h| h.as_array()))
+                .flatten()
+                .filter_map(|h| h.get("command").and_then(|c| c.as_str()))
+                .filter(|c| c.contains(BINARY_NAME))
+                .map(move |c| (event.clone(), c.to_string()))
+        })
+        .collect()
+}
+
+/// The command word naming the `session-reflect` binary, with `$VAR` and
+/// `${VAR}` expanded and bare names looked up on `PATH`.
+fn resolve_binary(command: &str, env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let word = command
+        .split_whitespace()
+        .map(|w| expand_vars(w.trim_matches(|c| c == '"' || c == '\''), env))
+        .find(|w| Path::new(w).file_name().is_some_and(|n| n == BINARY_NAME))?;
+
+    if word.contains('/') {
+        return Some(PathBuf::from(word)).filter(|p| p.is_file());
+    }
+    env("PATH")?
+        .split(':')
+        .map(|dir| Path::new(dir).join(&word))
+        .find(|p| p.is_file())
+}
+
+fn expand_vars(word: &str, env: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = word;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let (name, tail) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => (braced, ""),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        out.push_str(&env(name).unwrap_or_default());
+        rest = tail;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Last word of `<binary> --version`, e.g. `0.1.0` from `session-reflect 0.1.0`.
+fn binary_version(binary: &Path) -> Option<String> {
+    let output = Command::new(binary).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .last()
+        .map(String::from)
+}
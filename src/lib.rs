@@ -0,0 +1,102 @@
+//! Session reflection enforcement for Claude Code hooks.
+//!
+//! The binary in `src/bin` reads a hook payload from stdin and prints the
+//! decision; everything it does is exposed here so other hook dispatchers can
+//! embed the same logic and stay byte-compatible with its output.
+
+pub mod analysis;
+pub mod cli;
+pub mod clock;
+pub mod config;
+pub mod decision;
+pub mod input;
+pub mod install;
+pub mod output;
+pub mod pattern;
+pub mod state;
+pub mod syslog;
+
+#[cfg(test)]
+mod test_support;
+
+pub use analysis::{analyze_session, analyze_transcript, Analysis, MemoryWrite};
+pub use config::Config;
+pub use decision::{
+    decide, stop_outcome, stop_verdict, triggered_gates, Context, Decision, Gate, HookEvent,
+    HookInput, Outcome, ReasonCode, Verdict,
+};
+pub use output::{forward_output, render_output, write_output, OutputSchema};
+
+use config::RedactPaths;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by `--quiet` / `SESSION_REFLECT_QUIET`; silences all of stderr.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress every stderr diagnostic for the rest of the process. Stdout,
+/// which carries the hook protocol, is unaffected.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub(crate) fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// A `session-reflect: ` line on stderr, unless quiet.
+pub fn warn(message: std::fmt::Arguments) {
+    if !quiet() {
+        eprintln!("session-reflect: {}", message);
+    }
+}
+
+/// Process environment lookup, the production counterpart of the closures
+/// tests pass wherever an `env` parameter is taken.
+pub fn env_var(key: &str) -> Option<String> {
+    std::env::var(key).ok()
+}
+
+/// Diagnostic line on stderr when `debug` is enabled. Never touches stdout,
+/// which is reserved for the hook protocol.
+pub(crate) fn debug(config: &Config, message: std::fmt::Arguments) {
+    if config.debug {
+        warn(format_args!("{}", redact(&message.to_string(), config)));
+    }
+}
+
+/// Apply `redact_paths` to every occurrence of the home directory in `text`.
+/// A redacted path runs to the next whitespace, quote or closing bracket.
+fn redact(text: &str, config: &Config) -> String {
+    let home = match (&config.home, config.redact_paths) {
+        (Some(home), mode) if mode != RedactPaths::Off => home.trim_end_matches('/'),
+        _ => return text.to_string(),
+    };
+    if home.is_empty() {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(home) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + home.len()..];
+        // `/home/me` inside `/home/mel` isn't the home directory
+        if rest.starts_with(|c: char| c.is_alphanumeric() || c == '-' || c == '_' || c == '.') {
+            out.push_str(home);
+            continue;
+        }
+        out.push('~');
+        if config.redact_paths == RedactPaths::Hash {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "\"'`,)]}".contains(c))
+                .unwrap_or(rest.len());
+            let tail = rest[..end].trim_start_matches('/');
+            if !tail.is_empty() {
+                out.push_str(&format!("/{:08x}", analysis::fnv1a(tail.as_bytes()) as u32));
+            }
+            rest = &rest[end..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::debug;
+use crate::decision::{Decision, HookEvent};
+
+/// How long `forward_output` waits on a slow reader before giving up.
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// JSON shape of the hook output.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputSchema {
+    /// Top-level `additionalContext`, as the plugin has always emitted.
+    /// SessionStart, which Claude Code only reads nested, is always nested.
+    #[default]
+    Legacy,
+    /// Context nested under `hookSpecificOutput` with its `hookEventName`.
+    V2,
+}
+
+/// The exact stdout `session-reflect` prints for `decision` (without the
+/// trailing newline). `Allow` renders as an empty string — nothing is printed.
+pub fn render_output(decision: &Decision, schema: OutputSchema, pretty: bool) -> String {
+    let value = match to_json(decision, schema) {
+        Some(v) => v,
+        None => return String::new(),
+    };
+
+    if pretty {
+        serde_json::to_string_pretty(&value)
+    } else {
+        serde_json::to_string(&value)
+    }
+    .unwrap_or_default()
+}
+
+/// Write the hook output for `decision` to `out`, followed by a newline.
+/// Every hook response goes through here: an `Allow` must leave stdout
+/// empty, so it is checked in debug builds and never written in release.
+pub fn write_output<W: Write>(
+    out: &mut W,
+    decision: &Decision,
+    schema: OutputSchema,
+    pretty: bool,
+) -> io::Result<()> {
+    let output = render_output(decision, schema, pretty);
+    if matches!(decision, Decision::Allow) {
+        debug_assert!(output.is_empty(), "Allow rendered output: {}", output);
+        return Ok(());
+    }
+    if output.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "{}", output)
+}
+
+/// With `forward_socket`, send the hook output to that Unix socket as one
+/// line: the JSON printed on stdout, or `{}` for an `Allow`. Best-effort —
+/// a missing or stuck listener is logged under `debug` and otherwise ignored.
+pub fn forward_output(decision: &Decision, schema: OutputSchema, config: &Config) {
+    let socket = match &config.forward_socket {
+        Some(socket) => socket,
+        None => return,
+    };
+    let output = match render_output(decision, schema, false) {
+        output if output.is_empty() => "{}".to_string(),
+        output => output,
+    };
+    if let Err(e) = send(socket, &output) {
+        debug(
+            config,
+            format_args!("forward to {} failed: {}", socket.display(), e),
+        );
+    }
+}
+
+#[cfg(unix)]
+fn send(socket: &Path, line: &str) -> io::Result<()> {
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)?;
+    stream.set_write_timeout(Some(FORWARD_TIMEOUT))?;
+    writeln!(stream, "{}", line)
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &Path, _line: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix sockets need a Unix platform",
+    ))
+}
+
+fn to_json(decision: &Decision, schema: OutputSchema) -> Option<Value> {
+    let (mut value, code) = match decision {
+        Decision::Allow => return None,
+        Decision::Block { reason, code } => (
+            json!({
+                "decision": "block",
+                "reason": reason
+            }),
+            code,
+        ),
+        Decision::Inject {
+            event,
+            context,
+            code,
+        } => (
+            match schema {
+                OutputSchema::Legacy if *event != HookEvent::SessionStart => {
+                    json!({ "additionalContext": context })
+                }
+                _ => json!({
+                    "hookSpecificOutput": {
+                        "hookEventName": event,
+                        "additionalContext": context
+                    }
+                }),
+            },
+            code,
+        ),
+    };
+    // Legacy output stays byte-for-byte what it has always been
+    if schema == OutputSchema::V2 {
+        value["reason_code"] = json!(code);
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decision::ReasonCode;
+
+    #[test]
+    fn test_allow_renders_nothing() {
+        assert_eq!(render_output(&Decision::Allow, OutputSchema::V2, true), "");
+    }
+
+    #[test]
+    fn test_write_output_is_silent_on_allow() {
+        let mut out = Vec::new();
+        write_output(&mut out, &Decision::Allow, OutputSchema::Legacy, false).unwrap();
+        assert!(out.is_empty());
+
+        let block = Decision::Block {
+            reason: "reflect".to_string(),
+            code: ReasonCode::SubstantialNoMemory,
+        };
+        write_output(&mut out, &block, OutputSchema::Legacy, false).unwrap();
+        assert_eq!(out, b"{\"decision\":\"block\",\"reason\":\"reflect\"}\n");
+    }
+
+    #[test]
+    fn test_v2_nests_context() {
+        let decision = Decision::Inject {
+            event: HookEvent::PreCompact,
+            context: "reflect".to_string(),
+            code: ReasonCode::Precompact,
+        };
+        assert_eq!(
+            render_output(&decision, OutputSchema::Legacy, false),
+            r#"{"additionalContext":"reflect"}"#
+        );
+        assert_eq!(
+            render_output(&decision, OutputSchema::V2, false),
+            r#"{"hookSpecificOutput":{"additionalContext":"reflect","hookEventName":"PreCompact"},"reason_code":"PRECOMPACT"}"#
+        );
+    }
+
+    #[test]
+    fn test_session_start_is_nested_under_legacy() {
+        let decision = Decision::Inject {
+            event: HookEvent::SessionStart,
+            context: "resume".to_string(),
+            code: ReasonCode::SessionStart,
+        };
+        assert_eq!(
+            render_output(&decision, OutputSchema::Legacy, false),
+            r#"{"hookSpecificOutput":{"additionalContext":"resume","hookEventName":"SessionStart"}}"#
+        );
+    }
+
+    #[test]
+    fn test_v2_block_carries_reason_code() {
+        let decision = Decision::Block {
+            reason: "reflect".to_string(),
+            code: ReasonCode::SubstantialNoMemory,
+        };
+        assert_eq!(
+            render_output(&decision, OutputSchema::V2, false),
+            r#"{"decision":"block","reason":"reflect","reason_code":"SUBSTANTIAL_NO_MEMORY"}"#
+        );
+    }
+}
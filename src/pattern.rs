@@ -0,0 +1,357 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reflection pattern, relative to `cwd`.
+pub const PATTERN_PATH: &str = "Vaults/Personal/Orchestration/Patterns/Session Reflect.md";
+
+/// `:`-separated `pattern_paths`, e.g. for vaults that keep the pattern in
+/// different places.
+pub const PATTERN_ENV: &str = "SESSION_REFLECT_PATTERN";
+
+/// Load the reflection prompt from the first of `pattern_paths` that yields
+/// one, stripping frontmatter and H1. A `patterns` entry for `work_type` is
+/// tried before them.
+pub fn load_reflection_prompt(
+    cwd: &str,
+    pattern_paths: &[String],
+    patterns: &BTreeMap<String, String>,
+    work_type: Option<&str>,
+) -> Option<String> {
+    find_reflection_pattern(cwd, pattern_paths, patterns, work_type).map(|(_, prompt)| prompt)
+}
+
+/// Like `load_reflection_prompt`, along with the path of the file the
+/// prompt came from.
+pub fn find_reflection_pattern(
+    cwd: &str,
+    pattern_paths: &[String],
+    patterns: &BTreeMap<String, String>,
+    work_type: Option<&str>,
+) -> Option<(PathBuf, String)> {
+    work_type
+        .and_then(|label| patterns.get(label))
+        .into_iter()
+        .chain(pattern_paths)
+        .map(|path| Path::new(cwd).join(path))
+        .find_map(|path| load_pattern(&path).map(|prompt| (path, prompt)))
+}
+
+/// One pattern file's body (frontmatter and H1 stripped), if it has one.
+pub(crate) fn load_pattern(pattern_path: &Path) -> Option<String> {
+    let content = read_text(pattern_path)?;
+    let stripped = strip_frontmatter_and_h1(&content);
+
+    if stripped.trim().is_empty() {
+        None
+    } else {
+        Some(stripped.trim().to_string())
+    }
+}
+
+/// A configured separator with `\n` read as a newline and `\\` as a
+/// backslash, for values that can't easily hold a literal line break.
+pub fn unescape_separator(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                out.push('\\');
+                chars.next();
+            }
+            (c, _) => out.push(c),
+        }
+    }
+    out
+}
+
+/// Replace each `{{name}}` placeholder with its value. Unknown placeholders
+/// are left as written so a typo shows up in the prompt rather than vanishing.
+pub fn render_template(prompt: &str, vars: &[(&str, String)]) -> String {
+    vars.iter().fold(prompt.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{{{}}}}}", name), value)
+    })
+}
+
+/// Replace each `${NAME}` with `env(NAME)`. Unset variables are left as
+/// written, or removed with `blank_unset`. Lines inside ``` or ~~~ fences
+/// are copied untouched, since they're likely shell snippets.
+pub fn interpolate_env(
+    text: &str,
+    env: &dyn Fn(&str) -> Option<String>,
+    blank_unset: bool,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        if in_fence || trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            out.push_str(line);
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some(start) = rest.find("${") {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let name = after.find('}').map(|end| &after[..end]).filter(|name| {
+                name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+            match name {
+                Some(name) => {
+                    match env(name) {
+                        Some(value) => out.push_str(&value),
+                        None if blank_unset => {}
+                        None => out.push_str(&rest[start..start + name.len() + 3]),
+                    }
+                    rest = &after[name.len() + 1..];
+                }
+                None => {
+                    out.push_str("${");
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+    }
+    out
+}
+
+/// Replace `[[Target]]` with `Target` and `[[Target|Alias]]` with `Alias`,
+/// so an Obsidian-flavored prompt reads cleanly as plain text. Embeds
+/// (`![[...]]`) and unterminated brackets are left alone.
+pub fn resolve_wikilinks(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let is_embed = rest[..start].ends_with('!');
+        let end = match rest[start + 2..].find("]]") {
+            Some(end) if !is_embed => start + 2 + end,
+            _ => {
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                continue;
+            }
+        };
+        let link = &rest[start + 2..end];
+        let label = match link.split_once('|') {
+            Some((_, alias)) => alias,
+            None => link,
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(label);
+        rest = &rest[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Values of the `type`, `tag` and `tags` keys in leading YAML
+/// frontmatter, whether written as a scalar, an inline `[a, b]` list or a
+/// block list of `- item` lines.
+pub(crate) fn frontmatter_tags(content: &str) -> Vec<String> {
+    let mut lines = content.lines();
+    if lines.next().map(str::trim) != Some("---") {
+        return Vec::new();
+    }
+
+    let clean = |v: &str| {
+        v.trim()
+            .trim_matches(|c| c == '"' || c == '\'' || c == '#')
+            .to_string()
+    };
+    let mut tags = Vec::new();
+    let mut in_list = false;
+    for line in lines.take_while(|line| line.trim() != "---") {
+        if in_list {
+            if let Some(item) = line.trim().strip_prefix("- ") {
+                tags.push(clean(item));
+                continue;
+            }
+        }
+        in_list = false;
+        let (key, value) = match line.split_once(':') {
+            Some((k, v)) if !line.starts_with(char::is_whitespace) => (k.trim(), v.trim()),
+            _ => continue,
+        };
+        if !matches!(key, "type" | "tag" | "tags") {
+            continue;
+        }
+        match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            Some(items) => tags.extend(items.split(',').map(clean)),
+            None if value.is_empty() => in_list = true,
+            None => tags.push(clean(value)),
+        }
+    }
+    tags.retain(|t| !t.is_empty());
+    tags
+}
+
+/// Read a text file with `\r\n` and bare `\r` line endings normalized to `\n`.
+pub(crate) fn read_text(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|t| normalize_line_endings(&t))
+}
+
+fn normalize_line_endings(text: &str) -> String {
+    if text.contains('\r') {
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Remove YAML frontmatter (between first --- pair) and the first H1 line.
+pub fn strip_frontmatter_and_h1(content: &str) -> String {
+    let mut lines = content.lines();
+    let mut result = Vec::new();
+    let mut in_frontmatter = false;
+    let mut frontmatter_done = false;
+    let mut h1_removed = false;
+
+    // Check if first line is frontmatter delimiter
+    if let Some(first) = lines.next() {
+        if first.trim() == "---" {
+            in_frontmatter = true;
+        } else {
+            // No frontmatter — check if it's an H1
+            if first.starts_with("# ") && !h1_removed {
+                h1_removed = true;
+            } else {
+                result.push(first);
+            }
+            frontmatter_done = true;
+        }
+    }
+
+    for line in lines {
+        if in_frontmatter {
+            if line.trim() == "---" {
+                in_frontmatter = false;
+                frontmatter_done = true;
+            }
+            continue;
+        }
+
+        if frontmatter_done && !h1_removed && line.starts_with("# ") {
+            h1_removed = true;
+            continue;
+        }
+
+        result.push(line);
+    }
+
+    result.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_pattern_paths_fall_back_in_order() {
+        let dir = TempDir::new("pattern-fallback");
+        let second = dir.path().join("Patterns/Reflect.md");
+        fs::create_dir_all(second.parent().unwrap()).unwrap();
+        fs::write(&second, "# Reflect\n\nCapture learnings.\n").unwrap();
+        let cwd = dir.path().to_str().unwrap();
+        let paths = ["Missing.md", "Patterns/Reflect.md"].map(String::from);
+
+        let found = find_reflection_pattern(cwd, &paths, &BTreeMap::new(), None);
+        assert_eq!(found, Some((second, "Capture learnings.".to_string())));
+        assert_eq!(
+            load_reflection_prompt(cwd, &paths[..1], &BTreeMap::new(), None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_frontmatter_tags() {
+        let note = "---\ntype: learning\ntags: [rust, \"#hooks\"]\naliases:\n  - x\n---\nBody\n";
+        assert_eq!(frontmatter_tags(note), ["learning", "rust", "hooks"]);
+
+        let block = "---\ntags:\n  - decision\n  - infra\ntitle: T\n---\n";
+        assert_eq!(frontmatter_tags(block), ["decision", "infra"]);
+        assert!(frontmatter_tags("no frontmatter\ntype: learning\n").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_wikilinks() {
+        assert_eq!(
+            resolve_wikilinks("See [[Capture Rules]] and [[Memory/Decisions|the decision log]]."),
+            "See Capture Rules and the decision log."
+        );
+        assert_eq!(
+            resolve_wikilinks("![[Embed]] and [[open"),
+            "![[Embed]] and [[open"
+        );
+    }
+
+    #[test]
+    fn test_interpolate_env() {
+        let env = |key: &str| (key == "CLAUDE_PROJECT_DIR").then(|| "/work/acme".to_string());
+        let prompt = "Project ${CLAUDE_PROJECT_DIR}, owner ${OWNER}, cost $5 ${not a var}.\n\
+                      ```sh\necho ${CLAUDE_PROJECT_DIR}\n```\n";
+        assert_eq!(
+            interpolate_env(prompt, &env, false),
+            "Project /work/acme, owner ${OWNER}, cost $5 ${not a var}.\n\
+             ```sh\necho ${CLAUDE_PROJECT_DIR}\n```\n"
+        );
+        assert_eq!(interpolate_env("Owner: ${OWNER}!", &env, true), "Owner: !");
+    }
+
+    #[test]
+    fn test_unescape_separator() {
+        assert_eq!(unescape_separator(r"\n---\n"), "\n---\n");
+        assert_eq!(unescape_separator(r"a\\nb"), r"a\nb");
+        assert_eq!(unescape_separator("\n\n"), "\n\n");
+    }
+
+    #[test]
+    fn test_render_template_leaves_unknown_placeholders() {
+        let vars = [("tool_turns", "12".to_string())];
+        assert_eq!(
+            render_template("{{tool_turns}} turns, {{nope}}", &vars),
+            "12 turns, {{nope}}"
+        );
+    }
+
+    #[test]
+    fn test_strip_frontmatter_and_h1() {
+        let input = "---\ntitle: Test\n---\n# My Title\n\nBody text here.\n";
+        let result = strip_frontmatter_and_h1(input);
+        assert_eq!(result.trim(), "Body text here.");
+    }
+
+    #[test]
+    fn test_strip_h1_only() {
+        let input = "# My Title\n\nBody text here.\n";
+        let result = strip_frontmatter_and_h1(input);
+        assert_eq!(result.trim(), "Body text here.");
+    }
+
+    #[test]
+    fn test_no_frontmatter_no_h1() {
+        let input = "Just body text.\n";
+        let result = strip_frontmatter_and_h1(input);
+        assert_eq!(result.trim(), "Just body text.");
+    }
+
+    #[test]
+    fn test_strip_crlf_frontmatter() {
+        let input = "---\r\ntitle: Test\r\n---\r\n# My Title\r\n\r\nBody text here.\r\n";
+        let result = strip_frontmatter_and_h1(&normalize_line_endings(input));
+        assert_eq!(result, "\nBody text here.");
+    }
+}
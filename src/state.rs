@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::warn;
+
+/// Used verbatim as the state directory when set, for sandboxes where
+/// `HOME` is unusual or read-only.
+pub const STATE_DIR_ENV: &str = "SESSION_REFLECT_STATE_DIR";
+
+pub const STATE_FILE: &str = "state.json";
+const LOCK_FILE: &str = "state.lock";
+
+/// How long to wait for another invocation to release the lock.
+const LOCK_TIMEOUT: Duration = Duration::from_millis(500);
+/// A lock older than this was left behind by a crashed process.
+const STALE_LOCK: Duration = Duration::from_secs(5);
+
+/// Persistent state shared across invocations.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct State {
+    /// Recent Stop blocks, used to deduplicate racing Stop events.
+    pub recent_blocks: Vec<BlockRecord>,
+    /// Last full PreCompact injection per `cwd`, for `precompact_cooldown_secs`.
+    pub last_precompact: BTreeMap<String, u64>,
+    /// Transcripts of the current run of uncaptured substantial sessions,
+    /// per project (the discovered vault, else `cwd`), for
+    /// `block_after_streak`; at most that many are kept.
+    pub streaks: BTreeMap<String, Vec<String>>,
+    /// Per-project progress through `warmup_sessions`.
+    pub warmup: BTreeMap<String, Warmup>,
+    /// When a Stop last saw memory captured, per project, for
+    /// `max_capture_gap_hours`.
+    pub last_capture: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Warmup {
+    /// Transcripts of the substantial sessions let through so far.
+    pub sessions: Vec<String>,
+    /// A memory write ended the warmup early.
+    pub graduated: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockRecord {
+    /// Content hash of the transcript that was blocked.
+    pub hash: String,
+    /// When the block was issued (seconds since the epoch).
+    pub at: u64,
+}
+
+/// `$SESSION_REFLECT_STATE_DIR`, else `$XDG_STATE_HOME/session-reflect`,
+/// else `~/.local/state/session-reflect`. Created on first `update`.
+pub fn state_dir(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    if let Some(dir) = env(STATE_DIR_ENV).filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(dir) = env("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return Some(Path::new(&dir).join("session-reflect"));
+    }
+    env("HOME")
+        .filter(|h| !h.is_empty())
+        .map(|home| Path::new(&home).join(".local/state/session-reflect"))
+}
+
+/// Load, mutate, and persist the state under an exclusive lock. The file is
+/// replaced atomically (write to a temp file, then rename) so concurrent
+/// readers never see a partial write.
+pub fn update<T>(dir: &Path, f: impl FnOnce(&mut State) -> T) -> std::io::Result<T> {
+    fs::create_dir_all(dir)?;
+    let _lock = Lock::acquire(&dir.join(LOCK_FILE))?;
+
+    let path = dir.join(STATE_FILE);
+    let mut state: State = fs::read_to_string(&path)
+        .ok()
+        .and_then(|t| serde_json::from_str(&t).ok())
+        .unwrap_or_default();
+
+    let result = f(&mut state);
+
+    let tmp = dir.join(format!("{}.{}.tmp", STATE_FILE, std::process::id()));
+    fs::write(&tmp, serde_json::to_string(&state)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(result)
+}
+
+/// `update` for callers that must decide regardless. When the state can't
+/// be locked, read or saved, it warns on stderr; if `f` never ran, it runs
+/// against empty state, as if nothing had been recorded yet.
+pub fn update_best_effort<T>(dir: &Path, f: impl FnOnce(&mut State) -> T) -> T {
+    let mut f = Some(f);
+    let mut result = None;
+    if let Err(e) = update(dir, |state| result = f.take().map(|f| f(state))) {
+        warn(format_args!("state in {} not saved: {}", dir.display(), e));
+    }
+    match result {
+        Some(result) => result,
+        None => f.expect("update failed before running f")(&mut State::default()),
+    }
+}
+
+/// Lock file created with `create_new`, removed on drop.
+struct Lock(PathBuf);
+
+impl Lock {
+    fn acquire(path: &Path) -> std::io::Result<Lock> {
+        let start = SystemTime::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(_) => return Ok(Lock(path.to_path_buf())),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if is_stale(path) {
+                        let _ = fs::remove_file(path);
+                        continue;
+                    }
+                    if start.elapsed().unwrap_or_default() > LOCK_TIMEOUT {
+                        return Err(e);
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn is_stale(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_some_and(|age| age > STALE_LOCK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_update_persists_state() {
+        let dir = TempDir::new("state-update");
+        let record = BlockRecord {
+            hash: "abc".to_string(),
+            at: 42,
+        };
+        update(dir.path(), |s| s.recent_blocks.push(record.clone())).unwrap();
+
+        let seen = update(dir.path(), |s| s.recent_blocks.clone()).unwrap();
+        assert_eq!(seen, vec![record]);
+        assert!(!dir.path().join(LOCK_FILE).exists());
+    }
+}
@@ -0,0 +1,87 @@
+use std::io;
+use std::path::PathBuf;
+
+/// Where `log_syslog` lines go, injectable so tests can capture them.
+pub trait SystemLog {
+    /// Send one message; errors are the caller's to ignore.
+    fn send(&self, line: &str) -> io::Result<()>;
+}
+
+/// The local syslog daemon (journald included), reached through a Unix
+/// datagram socket — `/dev/log` by default.
+pub struct Syslog {
+    pub socket: PathBuf,
+}
+
+impl Default for Syslog {
+    fn default() -> Self {
+        Syslog {
+            socket: PathBuf::from("/dev/log"),
+        }
+    }
+}
+
+/// `user` facility, `info` severity.
+const PRIORITY: u8 = 14;
+
+/// The frame sent for `line`: priority, tag and pid, then the message.
+fn frame(line: &str) -> String {
+    format!(
+        "<{}>session-reflect[{}]: {}",
+        PRIORITY,
+        std::process::id(),
+        line
+    )
+}
+
+impl SystemLog for Syslog {
+    #[cfg(unix)]
+    fn send(&self, line: &str) -> io::Result<()> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.set_write_timeout(Some(std::time::Duration::from_millis(200)))?;
+        socket
+            .send_to(frame(line).as_bytes(), &self.socket)
+            .map(|_| ())
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _line: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no syslog on this platform",
+        ))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_syslog_sends_tagged_frame_to_socket() {
+        let dir = TempDir::new("syslog-socket");
+        let socket = dir.path().join("log");
+        let listener = UnixDatagram::bind(&socket).unwrap();
+
+        Syslog { socket }.send("event=Stop decision=allow").unwrap();
+
+        let mut buf = [0; 256];
+        let len = listener.recv(&mut buf).unwrap();
+        let expected = format!(
+            "<14>session-reflect[{}]: event=Stop decision=allow",
+            std::process::id()
+        );
+        assert_eq!(String::from_utf8_lossy(&buf[..len]), expected);
+    }
+
+    #[test]
+    fn test_syslog_send_fails_without_listener() {
+        let dir = TempDir::new("syslog-missing");
+        let syslog = Syslog {
+            socket: dir.path().join("log"),
+        };
+        assert!(syslog.send("nobody listening").is_err());
+    }
+}
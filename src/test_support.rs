@@ -0,0 +1,132 @@
+//! Fixtures shared by the unit tests.
+
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::analysis::{analyze_transcript, Analysis};
+use crate::clock::Clock;
+use crate::config::{Config, TOOL_TURN_THRESHOLD, USER_MSG_THRESHOLD};
+use crate::decision::{Context, HookInput};
+use crate::syslog::SystemLog;
+
+/// Scratch directory under the system temp dir, removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path =
+            std::env::temp_dir().join(format!("session-reflect-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A `$HOME` containing `Data/project` with the given transcript written
+/// to it; returns the home dir and a Stop input pointing at the project.
+pub fn stop_fixture(name: &str, transcript: &str) -> (TempDir, HookInput) {
+    let home = TempDir::new(name);
+    let cwd = home.path().join("Data/project");
+    fs::create_dir_all(&cwd).unwrap();
+    let transcript_path = cwd.join("transcript.jsonl");
+    fs::write(&transcript_path, transcript).unwrap();
+    let input = HookInput {
+        cwd: cwd.to_string_lossy().into_owned(),
+        transcript_path: transcript_path.to_string_lossy().into_owned(),
+        ..HookInput::default()
+    };
+    (home, input)
+}
+
+pub fn home_env(home: &TempDir) -> impl Fn(&str) -> Option<String> + '_ {
+    move |key| (key == "HOME").then(|| home.path().to_string_lossy().into_owned())
+}
+
+pub fn context<'a>(env: &'a dyn Fn(&str) -> Option<String>, clock: &'a dyn Clock) -> Context<'a> {
+    Context {
+        env,
+        clock,
+        syslog: &DiscardLog,
+    }
+}
+
+/// A syslog that drops everything.
+pub struct DiscardLog;
+
+impl SystemLog for DiscardLog {
+    fn send(&self, _line: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A syslog that keeps what it's sent.
+#[derive(Default)]
+pub struct RecordingLog(pub RefCell<Vec<String>>);
+
+impl SystemLog for RecordingLog {
+    fn send(&self, line: &str) -> io::Result<()> {
+        self.0.borrow_mut().push(line.to_string());
+        Ok(())
+    }
+}
+
+/// Enough user messages and tool turns to cross both default thresholds.
+pub fn substantial_transcript(extra: &[String]) -> String {
+    let mut lines = vec![r#"{"type":"human"}"#.to_string(); USER_MSG_THRESHOLD];
+    lines.extend((0..TOOL_TURN_THRESHOLD).map(|_| tool_turn("Bash")));
+    lines.extend(extra.iter().cloned());
+    lines.join("\n")
+}
+
+pub fn analyze(transcript: &str) -> Analysis {
+    analyze_transcript(transcript.as_bytes(), &Config::default())
+}
+
+pub fn memory_write_turn(tool: &str, file_path: &str) -> String {
+    format!(
+        r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","name":"{}","input":{{"file_path":"{}"}}}}]}}}}"#,
+        tool, file_path
+    )
+}
+
+pub fn tool_turn(name: &str) -> String {
+    format!(
+        r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","name":"{}","input":{{}}}}]}}}}"#,
+        name
+    )
+}
+
+pub fn patch_turn(field: &str, patch: &str) -> String {
+    let item = serde_json::json!({
+        "type": "tool_use",
+        "name": "apply_patch",
+        "input": { field: patch },
+    });
+    serde_json::json!({"type": "assistant", "message": {"content": [item]}}).to_string()
+}
+
+pub fn bash_turn(command: &str) -> String {
+    let item = serde_json::json!({
+        "type": "tool_use",
+        "name": "Bash",
+        "input": { "command": command },
+    });
+    serde_json::json!({"type": "assistant", "message": {"content": [item]}}).to_string()
+}
+
+/// A user entry whose content is the plain string `text`.
+pub fn user_message(text: &str) -> String {
+    serde_json::json!({"type": "user", "message": {"content": text}}).to_string()
+}
@@ -0,0 +1,306 @@
+mod common;
+
+use common::*;
+use std::fs;
+use std::io::Write;
+use std::process::{Output, Stdio};
+
+fn run(home: &TempDir, args: &[&str]) -> Output {
+    command(home.path(), &[]).args(args).output().unwrap()
+}
+
+fn json_stdout(output: &Output) -> serde_json::Value {
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn format_json_emits_valid_json() {
+    let home = TempDir::new("cli-format");
+    let dir = project_dir(&home);
+    let transcript = dir.join("session.jsonl");
+    fs::write(&transcript, substantial_transcript()).unwrap();
+    let dir = dir.to_str().unwrap();
+    let transcript = transcript.to_str().unwrap();
+
+    let report = json_stdout(&run(&home, &["replay", dir, "--format", "json"]));
+    assert_eq!(report["total"], 1);
+    assert_eq!(report["would_block"], 1);
+
+    let analysis = json_stdout(&run(&home, &["analyze", transcript]));
+    assert_eq!(analysis["user_messages"], 6);
+    assert_eq!(analysis["tool_using_turns"], 12);
+
+    let text = run(&home, &["analyze", transcript, "--format", "text"]);
+    assert!(String::from_utf8_lossy(&text.stdout).contains("tool_using_turns: 12"));
+}
+
+#[test]
+fn analyze_reads_transcript_from_stdin() {
+    let home = TempDir::new("cli-analyze-stdin");
+    for flag in ["-", "--stdin"] {
+        let mut child = command(home.path(), &[])
+            .args(["analyze", flag])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        stdin
+            .write_all(substantial_transcript().as_bytes())
+            .unwrap();
+        drop(stdin);
+
+        let analysis = json_stdout(&child.wait_with_output().unwrap());
+        assert_eq!(analysis["user_messages"], 6, "{}", flag);
+        assert_eq!(analysis["tool_using_turns"], 12, "{}", flag);
+    }
+}
+
+#[test]
+fn unknown_format_is_a_usage_error() {
+    let home = TempDir::new("cli-bad-format");
+    let output = run(&home, &["replay", ".", "--format", "yaml"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[cfg(unix)]
+#[test]
+fn verify_install_reports_stale_binary() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = TempDir::new("verify-install");
+    let bin = home.path().join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    let stub = bin.join("session-reflect");
+    fs::write(&stub, "#!/bin/sh\necho 'session-reflect 0.0.1'\n").unwrap();
+    fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let settings = home.path().join("settings.json");
+    let write_settings = |command: &str| {
+        let hooks = serde_json::json!([{
+            "matcher": "",
+            "hooks": [{ "type": "command", "command": command }],
+        }]);
+        let json = serde_json::json!({ "hooks": { "Stop": hooks } });
+        fs::write(&settings, json.to_string()).unwrap();
+    };
+    let verify = || {
+        let settings = settings.to_str().unwrap();
+        run(
+            &home,
+            &["verify-install", "--settings", settings, "--format", "json"],
+        )
+    };
+
+    write_settings(stub.to_str().unwrap());
+    let output = verify();
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["expected"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(report["hooks"][0]["version"], "0.0.1");
+
+    write_settings(env!("CARGO_BIN_EXE_session-reflect"));
+    let output = verify();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(settings.exists());
+}
+
+#[test]
+fn analyze_emits_prometheus_exposition() {
+    let home = TempDir::new("cli-prometheus");
+    let transcript = project_dir(&home).join("session.jsonl");
+    fs::write(&transcript, substantial_transcript()).unwrap();
+
+    let output = run(
+        &home,
+        &[
+            "analyze",
+            transcript.to_str().unwrap(),
+            "--format",
+            "prometheus",
+        ],
+    );
+    assert!(output.status.success());
+    let text = String::from_utf8(output.stdout).unwrap();
+
+    for line in text.lines() {
+        if line.starts_with('#') {
+            let mut words = line.split(' ');
+            assert!(matches!(words.nth(1), Some("HELP" | "TYPE")), "{}", line);
+            assert!(
+                words
+                    .next()
+                    .is_some_and(|m| m.starts_with("session_reflect_")),
+                "{}",
+                line
+            );
+        } else {
+            let (name, value) = line.split_once(' ').unwrap();
+            assert!(name.starts_with("session_reflect_"), "{}", line);
+            value.parse::<f64>().unwrap();
+        }
+    }
+    assert!(text.contains("\nsession_reflect_user_messages 6\n"));
+    assert!(text.contains("\nsession_reflect_tool_turns 12\n"));
+    assert!(text.contains("\nsession_reflect_blocked 1\n"));
+}
+
+#[test]
+fn config_dump_shows_env_over_file() {
+    let home = TempDir::new("cli-config");
+    let file = home.path().join("config.json");
+    fs::write(&file, r#"{"task_weight": 3, "tool_turn_threshold": 7}"#).unwrap();
+    let file = file.to_str().unwrap();
+
+    let env = [
+        ("SESSION_REFLECT_CONFIG", file),
+        ("SESSION_REFLECT_TASK_WEIGHT", "5"),
+    ];
+    let output = command(home.path(), &env).arg("config").output().unwrap();
+    let dump = json_stdout(&output);
+
+    assert_eq!(dump["config"]["task_weight"], 5);
+    assert_eq!(dump["sources"]["task_weight"], "env");
+    assert_eq!(dump["config"]["tool_turn_threshold"], 7);
+    assert_eq!(dump["sources"]["tool_turn_threshold"], "file");
+    assert_eq!(dump["sources"]["user_msg_threshold"], "default");
+}
+
+#[test]
+fn analyze_since_skips_earlier_entries() {
+    let home = TempDir::new("cli-since");
+    let transcript = home.path().join("session.jsonl");
+    let lines = [
+        r#"{"type":"user","timestamp":"2026-10-14T09:00:00Z","message":{"content":"morning"}}"#,
+        r#"{"type":"user","timestamp":"2026-10-14T14:00:00Z","message":{"content":"afternoon"}}"#,
+        r#"{"type":"user","message":{"content":"untimestamped"}}"#,
+    ];
+    fs::write(&transcript, lines.join("\n")).unwrap();
+    let path = transcript.to_str().unwrap();
+
+    let since = ["analyze", path, "--since", "2026-10-14T15:00:00+02:00"];
+    assert_eq!(json_stdout(&run(&home, &since))["user_messages"], 2);
+    let strict = [&since[..], &["--exclude-untimestamped"]].concat();
+    assert_eq!(json_stdout(&run(&home, &strict))["user_messages"], 1);
+    assert!(!run(&home, &["analyze", path, "--since", "after lunch"])
+        .status
+        .success());
+}
+
+#[test]
+fn analyze_exit_codes_fail_an_uncaptured_session() {
+    let home = TempDir::new("cli-analyze-exit");
+    let transcript = home.path().join("session.jsonl");
+    fs::write(&transcript, substantial_transcript()).unwrap();
+    let path = transcript.to_str().unwrap();
+
+    let plain = run(&home, &["analyze", path]);
+    assert_eq!(plain.status.code(), Some(0));
+    let gated = run(&home, &["analyze", path, "--exit-codes"]);
+    assert_eq!(gated.status.code(), Some(2));
+    assert_eq!(gated.stdout, plain.stdout);
+
+    let env = [("SESSION_REFLECT_EXIT_CODES", "1")];
+    let output = command(home.path(), &env)
+        .args(["analyze", path])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+
+    // The hook lets a sentinel-excused session through, so CI does too
+    let sentinel = r#"{"type":"user","message":{"content":"[[session-reflect:allow]]"}}"#;
+    fs::write(
+        &transcript,
+        format!("{}\n{}", substantial_transcript(), sentinel),
+    )
+    .unwrap();
+    let excused = run(&home, &["analyze", path, "--exit-codes"]);
+    assert_eq!(excused.status.code(), Some(0));
+}
+
+#[test]
+fn unset_home_warns_and_allows_unless_root_is_set() {
+    let home = TempDir::new("cli-no-home");
+    let cwd = project_dir(&home);
+    let transcript = cwd.join("transcript.jsonl");
+    fs::write(&transcript, substantial_transcript()).unwrap();
+    let payload = serde_json::json!({ "cwd": cwd, "transcript_path": transcript }).to_string();
+    let hook = |env: &[(&str, &str)]| {
+        let mut child = command(home.path(), env)
+            .env_remove("HOME")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(payload.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = hook(&[]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("HOME is unset and SESSION_REFLECT_ROOT isn't set"),
+        "{}",
+        stderr
+    );
+
+    let root = home.path().join("Data");
+    let output = hook(&[("SESSION_REFLECT_ROOT", root.to_str().unwrap())]);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    let decision: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(decision["decision"], "block");
+}
+
+#[test]
+fn quiet_silences_stderr_but_not_the_decision() {
+    let home = TempDir::new("cli-quiet");
+    let cwd = project_dir(&home);
+    let transcript = cwd.join("transcript.jsonl");
+    fs::write(&transcript, substantial_transcript()).unwrap();
+    let config = home.path().join("config.json");
+    fs::write(&config, r#"{"active_hours": "after lunch"}"#).unwrap();
+    let payload = serde_json::json!({ "cwd": cwd, "transcript_path": transcript }).to_string();
+    let env = [
+        ("SESSION_REFLECT_CONFIG", config.to_str().unwrap()),
+        ("SESSION_REFLECT_DEDUPE_WINDOW_SECS", "0"),
+    ];
+
+    let loud = run_hook(home.path(), &env, &payload);
+    assert!(String::from_utf8_lossy(&loud.stderr).contains("active_hours"));
+
+    let mut child = command(home.path(), &env)
+        .arg("--quiet")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(payload.as_bytes())
+        .unwrap();
+    let flagged = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&flagged.stderr), "");
+    assert_eq!(flagged.stdout, loud.stdout);
+
+    let quiet_env = [&env[..], &[("SESSION_REFLECT_QUIET", "1")]].concat();
+    let quiet = run_hook(home.path(), &quiet_env, &payload);
+    assert_eq!(String::from_utf8_lossy(&quiet.stderr), "");
+    assert_eq!(quiet.stdout, loud.stdout);
+    assert!(!loud.stdout.is_empty());
+
+    let usage = run(&home, &["-q", "no-such-command"]);
+    assert_eq!(usage.status.code(), Some(2));
+    assert!(usage.stderr.is_empty());
+}
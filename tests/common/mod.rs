@@ -0,0 +1,85 @@
+//! Helpers for driving the compiled binary.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// Scratch directory under the system temp dir, removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!(
+            "session-reflect-it-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// `session-reflect` with a clean environment: only `HOME` and `PATH`, plus
+/// whatever `env` adds.
+pub fn command(home: &Path, env: &[(&str, &str)]) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_session-reflect"));
+    cmd.env_clear()
+        .env("HOME", home)
+        .env("PATH", std::env::var("PATH").unwrap_or_default());
+    for (key, value) in env {
+        cmd.env(key, value);
+    }
+    cmd
+}
+
+/// Run the hook with `payload` on stdin.
+pub fn run_hook(home: &Path, env: &[(&str, &str)], payload: &str) -> Output {
+    let mut child = command(home, env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(payload.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+pub fn tool_turn(name: &str) -> String {
+    format!(
+        r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","name":"{}","input":{{}}}}]}}}}"#,
+        name
+    )
+}
+
+/// Comfortably past both default thresholds, with no memory writes.
+pub fn substantial_transcript() -> String {
+    let mut lines = vec![r#"{"type":"human"}"#.to_string(); 6];
+    lines.extend((0..12).map(|_| tool_turn("Bash")));
+    lines.join("\n")
+}
+
+/// `$HOME/Data/project`, created.
+pub fn project_dir(home: &TempDir) -> PathBuf {
+    let cwd = home.path().join("Data/project");
+    fs::create_dir_all(&cwd).unwrap();
+    cwd
+}
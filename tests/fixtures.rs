@@ -0,0 +1,106 @@
+//! Data-driven scenarios: every directory under `tests/fixtures/` is one
+//! hook invocation run through the binary.
+//!
+//! A scenario holds:
+//! - `input.json`: the hook payload. `cwd` defaults to `$HOME/Data/project`
+//!   and `transcript_path` to the scenario's transcript, if it has one.
+//! - `transcript.jsonl` (optional): copied into the project directory.
+//! - `env.json` (optional): extra environment variables, as strings.
+//! - `config.json` (optional): written out and named by `SESSION_REFLECT_CONFIG`.
+//! - `expected.json`: the JSON the hook prints, or `null` for no output.
+
+mod common;
+
+use common::*;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+fn read_json(path: &Path) -> Option<Value> {
+    let text = fs::read_to_string(path).ok()?;
+    Some(serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)))
+}
+
+/// Run one scenario and return the parsed stdout (`null` when empty).
+fn run_fixture(name: &str, dir: &Path) -> Value {
+    let home = TempDir::new(&format!("fixture-{}", name));
+    let cwd = project_dir(&home);
+
+    let mut input = read_json(&dir.join("input.json")).expect("input.json is required");
+    let payload = input.as_object_mut().expect("input.json must be an object");
+    payload
+        .entry("cwd")
+        .or_insert_with(|| cwd.to_string_lossy().into());
+    let transcript = dir.join("transcript.jsonl");
+    if transcript.exists() {
+        let copy = cwd.join("transcript.jsonl");
+        fs::copy(&transcript, &copy).unwrap();
+        payload
+            .entry("transcript_path")
+            .or_insert_with(|| copy.to_string_lossy().into());
+    }
+
+    let mut env: Vec<(String, String)> = read_json(&dir.join("env.json"))
+        .map(|v| {
+            v.as_object()
+                .expect("env.json must be an object")
+                .iter()
+                .map(|(k, v)| {
+                    (
+                        k.clone(),
+                        v.as_str().expect("env values are strings").into(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(config) = read_json(&dir.join("config.json")) {
+        let path = home.path().join("config.json");
+        fs::write(&path, config.to_string()).unwrap();
+        env.push((
+            "SESSION_REFLECT_CONFIG".into(),
+            path.to_string_lossy().into(),
+        ));
+    }
+    let env: Vec<(&str, &str)> = env.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+
+    let output = run_hook(home.path(), &env, &input.to_string());
+    assert!(
+        output.status.success(),
+        "{}: exited {}",
+        name,
+        output.status
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    if stdout.trim().is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{}: {}: {}", name, e, stdout))
+    }
+}
+
+#[test]
+fn fixtures_match_expected_decisions() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut scenarios: Vec<_> = fs::read_dir(&root)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    scenarios.sort();
+    assert!(!scenarios.is_empty(), "no scenarios in {}", root.display());
+
+    let mut failures = Vec::new();
+    for dir in &scenarios {
+        let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+        let expected = read_json(&dir.join("expected.json")).expect("expected.json is required");
+        let actual = run_fixture(&name, dir);
+        if actual != expected {
+            failures.push(format!(
+                "{}:\n  expected {}\n  actual   {}",
+                name, expected, actual
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,205 @@
+mod common;
+
+use common::*;
+use session_reflect::decision::{FALLBACK_REASON, PRECOMPACT_PREFIX, SESSION_START_RESUME};
+use session_reflect::{write_output, Decision, HookEvent, OutputSchema, ReasonCode};
+use std::fs;
+
+fn stdout_of(home: &TempDir, env: &[(&str, &str)], payload: &serde_json::Value) -> String {
+    let output = run_hook(home.path(), env, &payload.to_string());
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// What `main` prints for a decision: the rendered JSON plus a newline, or nothing.
+fn printed(decision: &Decision, schema: OutputSchema, pretty: bool) -> String {
+    let mut out = Vec::new();
+    write_output(&mut out, decision, schema, pretty).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn render_output_matches_binary_stdout() {
+    let home = TempDir::new("render");
+    let cwd = project_dir(&home);
+    let transcript = cwd.join("transcript.jsonl");
+    fs::write(&transcript, substantial_transcript()).unwrap();
+
+    let stop = serde_json::json!({ "cwd": cwd, "transcript_path": transcript });
+    let compact = serde_json::json!({ "cwd": cwd, "trigger": "auto" });
+    let outside = serde_json::json!({ "cwd": "/elsewhere", "transcript_path": transcript });
+
+    let block = Decision::Block {
+        reason: FALLBACK_REASON.to_string(),
+        code: ReasonCode::SubstantialNoMemory,
+    };
+    let inject = Decision::Inject {
+        event: HookEvent::PreCompact,
+        context: format!("{}{}", PRECOMPACT_PREFIX, FALLBACK_REASON),
+        code: ReasonCode::Precompact,
+    };
+
+    for (schema, name) in [(OutputSchema::Legacy, "legacy"), (OutputSchema::V2, "v2")] {
+        for pretty in [false, true] {
+            let pretty_flag = if pretty { "1" } else { "0" };
+            let env = [
+                ("SESSION_REFLECT_OUTPUT_SCHEMA", name),
+                ("SESSION_REFLECT_PRETTY", pretty_flag),
+                ("SESSION_REFLECT_DEDUPE_WINDOW_SECS", "0"),
+            ];
+
+            assert_eq!(
+                stdout_of(&home, &env, &stop),
+                printed(&block, schema, pretty)
+            );
+            assert_eq!(
+                stdout_of(&home, &env, &compact),
+                printed(&inject, schema, pretty)
+            );
+            assert_eq!(
+                stdout_of(&home, &env, &outside),
+                printed(&Decision::Allow, schema, pretty)
+            );
+        }
+    }
+    assert_eq!(stdout_of(&home, &[], &outside), "");
+}
+
+#[test]
+fn debug_output_redacts_home_paths() {
+    let home = TempDir::new("redact");
+    let cwd = project_dir(&home);
+    let note = cwd.join("Memory/Learnings/note.md");
+    let write = format!(
+        r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","name":"Write","input":{{"file_path":"{}"}}}}]}}}}"#,
+        note.display()
+    );
+    let transcript = cwd.join("transcript.jsonl");
+    fs::write(&transcript, substantial_transcript() + "\n" + &write).unwrap();
+    let payload = serde_json::json!({ "cwd": cwd, "transcript_path": transcript }).to_string();
+    let stderr = |mode: &str| {
+        let env = [
+            ("SESSION_REFLECT_DEBUG", "1"),
+            ("SESSION_REFLECT_REDACT_PATHS", mode),
+        ];
+        String::from_utf8(run_hook(home.path(), &env, &payload).stderr).unwrap()
+    };
+    let home_path = home.path().to_string_lossy().into_owned();
+
+    let plain = stderr("off");
+    assert!(plain.contains(&format!("memory write credited: Write {}", note.display())));
+
+    let redacted = stderr("home");
+    assert!(
+        redacted.contains("memory write credited: Write ~/Data/project/Memory/Learnings/note.md")
+    );
+    assert!(!redacted.contains(&home_path));
+
+    let hashed = stderr("hash");
+    assert!(hashed.contains("memory write credited: Write ~/"));
+    assert!(!hashed.contains("Data/project"));
+}
+
+#[test]
+fn per_event_output_settings_override_global() {
+    let home = TempDir::new("per-event");
+    let cwd = project_dir(&home);
+    let config = home.path().join("config.json");
+    fs::write(
+        &config,
+        r#"{"output_schema":"legacy","always_inject_on_stop":true,"precompact":{"output_schema":"v2"}}"#,
+    )
+    .unwrap();
+    let config = config.to_string_lossy().into_owned();
+    let env = [("SESSION_REFLECT_CONFIG", config.as_str())];
+
+    let stop = serde_json::json!({ "cwd": cwd });
+    let compact = serde_json::json!({ "cwd": cwd, "trigger": "auto" });
+    let stop_inject = Decision::Inject {
+        event: HookEvent::Stop,
+        context: FALLBACK_REASON.to_string(),
+        code: ReasonCode::AlwaysInject,
+    };
+    let compact_inject = Decision::Inject {
+        event: HookEvent::PreCompact,
+        context: format!("{}{}", PRECOMPACT_PREFIX, FALLBACK_REASON),
+        code: ReasonCode::Precompact,
+    };
+
+    assert_eq!(
+        stdout_of(&home, &env, &stop),
+        printed(&stop_inject, OutputSchema::Legacy, false)
+    );
+    assert_eq!(
+        stdout_of(&home, &env, &compact),
+        printed(&compact_inject, OutputSchema::V2, false)
+    );
+}
+
+#[test]
+fn session_start_context_is_nested_by_default() {
+    let home = TempDir::new("session-start");
+    let cwd = project_dir(&home);
+    let resume = serde_json::json!({
+        "cwd": cwd,
+        "hook_event_name": "SessionStart",
+        "source": "resume",
+    });
+
+    let printed: serde_json::Value = serde_json::from_str(&stdout_of(&home, &[], &resume)).unwrap();
+    assert_eq!(
+        printed,
+        serde_json::json!({
+            "hookSpecificOutput": {
+                "hookEventName": "SessionStart",
+                "additionalContext": SESSION_START_RESUME,
+            }
+        })
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn forward_socket_receives_block_json() {
+    use std::io::BufRead;
+    use std::os::unix::net::UnixListener;
+
+    let home = TempDir::new("forward-socket");
+    let cwd = project_dir(&home);
+    let transcript = cwd.join("transcript.jsonl");
+    fs::write(&transcript, substantial_transcript()).unwrap();
+    let socket = home.path().join("supervisor.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    let received = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        std::io::BufReader::new(stream)
+            .read_line(&mut line)
+            .unwrap();
+        line
+    });
+
+    let env = [("SESSION_REFLECT_FORWARD_SOCKET", socket.to_str().unwrap())];
+    let stop = serde_json::json!({ "cwd": cwd, "transcript_path": transcript });
+    let printed = stdout_of(&home, &env, &stop);
+
+    assert!(printed.contains(r#""decision":"block""#));
+    assert_eq!(received.join().unwrap(), printed);
+}
+
+#[test]
+fn forward_socket_failure_leaves_output_alone() {
+    let home = TempDir::new("forward-missing");
+    let cwd = project_dir(&home);
+    let transcript = cwd.join("transcript.jsonl");
+    fs::write(&transcript, substantial_transcript()).unwrap();
+    let missing = home.path().join("nobody.sock");
+
+    let stop = serde_json::json!({ "cwd": cwd, "transcript_path": transcript });
+    let plain = stdout_of(&home, &[("SESSION_REFLECT_DEDUPE_WINDOW_SECS", "0")], &stop);
+    let env = [
+        ("SESSION_REFLECT_DEDUPE_WINDOW_SECS", "0"),
+        ("SESSION_REFLECT_FORWARD_SOCKET", missing.to_str().unwrap()),
+    ];
+    assert_eq!(stdout_of(&home, &env, &stop), plain);
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::*;
+use std::process::Stdio;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn open_stdin_times_out_with_success() {
+    let home = TempDir::new("stdin-timeout");
+    let mut child = command(home.path(), &[("SESSION_REFLECT_STDIN_TIMEOUT_MS", "100")])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Held open (never written or closed) until the child is done.
+    let _stdin = child.stdin.take();
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if start.elapsed() > Duration::from_secs(10) {
+            child.kill().unwrap();
+            panic!("hook hung on open stdin");
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    assert!(status.success());
+    let output = child.wait_with_output().unwrap();
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no complete input"));
+}